@@ -1,19 +1,42 @@
 use embedded_hal::adc::OneShot;
 use embedded_hal::digital::v2::InputPin;
-use fixed::types::U0F16;
+use fixed::types::{I32F32, U0F16};
 use rp2040_hal::{
-    adc::AdcPin,
+    adc::{AdcPin, TempSense},
     gpio::{bank0::*, DynFunction, DynPinId, FunctionSioInput, Pin, PullDown, PullUp},
-    Adc,
+    Adc, Timer,
 };
 
-use trumpet_synth::io::{self};
+use trumpet_synth::{
+    environment::Celsius,
+    io::{self},
+    trumpet::{SlideExtension, ValvePosition},
+};
 
 pub struct SioFifo(pub rp2040_hal::sio::SioFifo);
 
+/// The FIFO to the synth core is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FifoFull;
+
 impl io::Fifo for SioFifo {
-    fn write(&mut self, value: u32) {
+    type Error = FifoFull;
+
+    fn write(&mut self, value: u32) -> Result<(), FifoFull> {
+        if !self.0.is_write_ready() {
+            return Err(FifoFull);
+        }
+
         self.0.write(value);
+        Ok(())
+    }
+}
+
+pub struct Rp2040Clock(pub Timer);
+
+impl io::Clock for Rp2040Clock {
+    fn now(&mut self) -> io::Millis {
+        (self.0.get_counter().ticks() / 1000) as io::Millis
     }
 }
 
@@ -22,28 +45,108 @@ pub struct Rp2040Inputs {
     pub blow_pin: Pin<Gpio0, FunctionSioInput, PullUp>,
     pub adc: Adc,
     pub adc_pins: [AdcPin<Pin<DynPinId, DynFunction, PullDown>>; 2],
+    /// Hall effect sensors measuring the valve positions, valves without one
+    /// are read from their pushbutton.
+    pub valve_sensors: [Option<AdcPin<Pin<DynPinId, DynFunction, PullDown>>>; 3],
+    /// Linear pot moving the first valve slide, or the main slide of a slide
+    /// instrument.
+    pub slide_pot: Option<AdcPin<Pin<DynPinId, DynFunction, PullDown>>>,
+    pub temp_sensor: TempSense,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputError {
+    Pin,
+    Adc,
 }
 
 fn convert_adc_value(read: u16) -> U0F16 {
     U0F16::from_bits(read << 4)
 }
 
+/// Conversion from the RP2040 datasheet: the sensor reads 0.706V at 27°C and
+/// drops 1.721mV per degree.
+fn convert_temperature(read: u16) -> Celsius {
+    let millivolts = I32F32::from_num(read) * 3300 / 4096;
+    let temperature =
+        I32F32::from_num(27) - (millivolts - I32F32::from_num(706)) / I32F32::from_num(1.721);
+    Celsius::saturating_from_num(temperature)
+}
+
 impl io::Inputs for Rp2040Inputs {
-    fn valve(&mut self, valve: trumpet_synth::trumpet::Valve) -> bool {
-        self.valve_pins[valve as usize].is_low().unwrap()
+    type Error = InputError;
+
+    fn valve(&mut self, valve: trumpet_synth::trumpet::Valve) -> Result<bool, InputError> {
+        if self
+            .valve_sensors
+            .get(valve as usize)
+            .is_some_and(|sensor| sensor.is_some())
+        {
+            return Ok(self.valve_position(valve)? > ValvePosition::MAX >> 1);
+        }
+
+        match self.valve_pins.get(valve as usize) {
+            Some(pin) => pin.is_low().map_err(|_| InputError::Pin),
+            None => Ok(false),
+        }
+    }
+
+    fn blow(&mut self) -> Result<bool, InputError> {
+        self.blow_pin.is_low().map_err(|_| InputError::Pin)
     }
 
-    fn blow(&mut self) -> bool {
-        self.blow_pin.is_low().unwrap()
+    fn embouchure(&mut self) -> Result<trumpet_synth::trumpet::Embouchure, InputError> {
+        let adc0_read: u16 = self
+            .adc
+            .read(&mut self.adc_pins[1])
+            .map_err(|_| InputError::Adc)?;
+        Ok(convert_adc_value(adc0_read))
     }
 
-    fn embouchure(&mut self) -> trumpet_synth::trumpet::Embouchure {
-        let adc0_read: u16 = self.adc.read(&mut self.adc_pins[1]).unwrap();
-        convert_adc_value(adc0_read)
+    fn blowstrength(&mut self) -> Result<trumpet_synth::trumpet::BlowStrength, InputError> {
+        let adc1_read: u16 = self
+            .adc
+            .read(&mut self.adc_pins[0])
+            .map_err(|_| InputError::Adc)?;
+        Ok(convert_adc_value(adc1_read))
+    }
+
+    fn slide(
+        &mut self,
+        valve: trumpet_synth::trumpet::Valve,
+    ) -> Result<SlideExtension, InputError> {
+        match (valve, &mut self.slide_pot) {
+            (trumpet_synth::trumpet::Valve::First, Some(pot)) => {
+                let read: u16 = self.adc.read(pot).map_err(|_| InputError::Adc)?;
+                Ok(convert_adc_value(read))
+            }
+            _ => Ok(SlideExtension::ZERO),
+        }
+    }
+
+    fn valve_position(
+        &mut self,
+        valve: trumpet_synth::trumpet::Valve,
+    ) -> Result<ValvePosition, InputError> {
+        if let Some(Some(sensor)) = self.valve_sensors.get_mut(valve as usize) {
+            let read: u16 = self.adc.read(sensor).map_err(|_| InputError::Adc)?;
+            return Ok(convert_adc_value(read));
+        }
+
+        Ok(if self.valve(valve)? {
+            ValvePosition::MAX
+        } else {
+            ValvePosition::ZERO
+        })
     }
 
-    fn blowstrength(&mut self) -> trumpet_synth::trumpet::BlowStrength {
-        let adc1_read: u16 = self.adc.read(&mut self.adc_pins[0]).unwrap();
-        convert_adc_value(adc1_read)
+    /// The internal sensor of the RP2040, which follows the air around the
+    /// instrument with some delay and offset from the chip heating itself.
+    fn temperature(&mut self) -> Result<Option<Celsius>, InputError> {
+        let read: u16 = self
+            .adc
+            .read(&mut self.temp_sensor)
+            .map_err(|_| InputError::Adc)?;
+        Ok(Some(convert_temperature(read)))
     }
 }
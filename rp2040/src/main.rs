@@ -3,6 +3,7 @@
 
 pub mod io;
 pub mod rgb;
+pub mod storage;
 
 #[link_section = ".boot2"]
 #[no_mangle]
@@ -14,8 +15,10 @@ use cortex_m::singleton;
 #[cfg(feature = "defmt")]
 use defmt::{error, info, warn};
 use defmt_rtt as _;
+use embedded_hal::digital::v2::InputPin;
+use fixed::types::U8F8;
 use fugit::HertzU32;
-use io::{Rp2040Inputs, SioFifo};
+use io::{Rp2040Clock, Rp2040Inputs, SioFifo};
 #[allow(unused_imports)]
 #[cfg(not(feature = "defmt"))]
 use log::{error, info, warn};
@@ -42,12 +45,23 @@ use rp2040_hal::{
     sio::Sio,
     watchdog::Watchdog,
     xosc::setup_xosc_blocking,
-    Adc,
+    Adc, Timer,
 };
+use storage::FlashStorage;
 
 use common::consts::*;
 use rytmos_synth::{commands::Command, synth::Synth};
-use trumpet_synth::{interface::TrumpetInterface, io::IO};
+use trumpet_synth::{
+    analog::{AnalogChannel, ChannelSettings, Smoothing},
+    interface::{TrumpetInterface, ValveMode},
+    io::IO,
+    presets,
+    settings::SettingsGesture,
+    trumpet::Valve,
+};
+
+#[cfg(all(feature = "hall-valve", feature = "slide-pot"))]
+compile_error!("the hall valve sensor and the slide pot both use gpio28");
 
 static mut CORE1_STACK: Stack<4096> = Stack::new();
 
@@ -239,6 +253,9 @@ fn synth_core(sys_freq: u32) -> ! {
         }
 
         i2s_tx_transfer = next_tx_transfer.read_next(next_tx_buf);
+
+        // Settings are being saved to flash
+        storage::pause_point();
     }
 }
 
@@ -323,7 +340,7 @@ fn main() -> ! {
             .unwrap();
     }
 
-    let mut _delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
 
     // Setup the other core
     let sys_freq = clocks.system_clock.freq().to_Hz();
@@ -350,12 +367,51 @@ fn main() -> ! {
 
     let blow_pin: gpio::Pin<Gpio0, FunctionSioInput, PullUp> = pins.gpio0.reconfigure();
 
+    // Let the pull ups settle before reading the buttons held during power on
+    delay.delay_ms(10);
+
+    // The instrument is selected by holding a fingering while powering on,
+    // read as a binary number with the first valve as lowest bit and blow as highest.
+    let mut preset_index = 0;
+    for (bit, pin) in valve_pins.iter().enumerate() {
+        if pin.is_low().unwrap() {
+            preset_index |= 1 << bit;
+        }
+    }
+    if blow_pin.is_low().unwrap() {
+        preset_index |= 1 << valve_pins.len();
+    }
+
+    let preset = presets::by_index(preset_index).unwrap_or(&presets::PRESETS[0]);
+    info!("Selected instrument: {}", preset.name);
+
     let adc_pins: [AdcPin<Pin<DynPinId, DynFunction, PullDown>>; 2] = [
         AdcPin::new(pins.gpio26.reconfigure().into_dyn_pin()).unwrap(),
         AdcPin::new(pins.gpio27.reconfigure().into_dyn_pin()).unwrap(),
     ];
 
-    let adc = Adc::new(pac.ADC, &mut pac.RESETS);
+    // The current PCB has pushbutton valves only. With the `hall-valve` feature
+    // a hall effect sensor on the remaining ADC pin (gpio28) measures the first
+    // valve, the ADC has no pins left for the others.
+    #[cfg(feature = "hall-valve")]
+    let valve_sensors: [Option<AdcPin<Pin<DynPinId, DynFunction, PullDown>>>; 3] = [
+        Some(AdcPin::new(pins.gpio28.reconfigure().into_dyn_pin()).unwrap()),
+        None,
+        None,
+    ];
+    #[cfg(not(feature = "hall-valve"))]
+    let valve_sensors: [Option<AdcPin<Pin<DynPinId, DynFunction, PullDown>>>; 3] =
+        [None, None, None];
+
+    // With the `slide-pot` feature a linear pot on the same pin moves the slide
+    #[cfg(feature = "slide-pot")]
+    let slide_pot = Some(AdcPin::new(pins.gpio28.reconfigure().into_dyn_pin()).unwrap());
+    #[cfg(not(feature = "slide-pot"))]
+    let slide_pot: Option<AdcPin<Pin<DynPinId, DynFunction, PullDown>>> = None;
+    let analog_valves = valve_sensors.iter().any(|sensor| sensor.is_some());
+
+    let mut adc = Adc::new(pac.ADC, &mut pac.RESETS);
+    let temp_sensor = adc.take_temp_sensor().unwrap();
 
     let r: gpio::Pin<Gpio10, FunctionPwm, PullUp> = pins.gpio10.reconfigure();
     let g: gpio::Pin<Gpio11, FunctionPwm, PullUp> = pins.gpio11.reconfigure();
@@ -386,13 +442,65 @@ fn main() -> ! {
             blow_pin,
             adc,
             adc_pins,
+            valve_sensors,
+            slide_pot,
+            temp_sensor,
         },
+        clock: Rp2040Clock(Timer::new(pac.TIMER, &mut pac.RESETS, &clocks)),
     };
 
-    let mut interface = TrumpetInterface::new(io, 10);
+    let mut interface = TrumpetInterface::with_preset(io, 5, preset);
+    if analog_valves {
+        interface.set_valve_mode(ValveMode::Analog);
+    }
+
+    // The pots jitter a few ADC steps, smoothing them keeps the pitch steady
+    // without making quick lip slurs lag
+    let pot_conditioning = ChannelSettings {
+        smoothing: Smoothing::OneEuro {
+            min_cutoff: U8F8::ONE,
+            beta: U8F8::unwrapped_from_str("0.5"),
+        },
+        threshold: ChannelSettings::DEFAULT_THRESHOLD,
+    };
+    interface.set_conditioning(AnalogChannel::Embouchure, pot_conditioning);
+    interface.set_conditioning(AnalogChannel::Blowstrength, pot_conditioning);
+    interface.set_conditioning(AnalogChannel::Slide(Valve::First), pot_conditioning);
+
+    let mut settings = SettingsGesture::load(FlashStorage, &mut interface);
 
     loop {
-        interface.run();
+        // Faulty inputs hold their last reading and commands the full FIFO
+        // refused are sent next time, so the instrument keeps playing
+        interface.run().ok();
+
+        // Settings and the calibration are saved even with a faulty input
+        let selection = settings.update(&mut interface);
+
+        // While calibrating the pots the LED turns purple, with a faulty input it
+        // turns orange. While changing settings the LED turns blue, brighter for
+        // higher options. Otherwise it is a tuner: green when in tune, turning
+        // red the further off the note is
+        if interface.calibrating() {
+            rgb.color(5, 0, 5);
+            continue;
+        }
+
+        if !interface.input_faults().is_empty() {
+            rgb.color(5, 2, 0);
+            continue;
+        }
+
+        match (selection, interface.trumpet().concert_pitch()) {
+            (Some((option, options)), _) => {
+                rgb.color(0, 0, (2 + option * 30 / options) as u8);
+            }
+            (None, Some(pitch)) => {
+                let off = pitch.cents.abs().to_num::<u32>().min(50) as u8;
+                rgb.color(off / 5, (50 - off) / 5, 0);
+            }
+            (None, None) => rgb.color(5, 0, 0),
+        }
         // let state = TrumpetInputState::read_from(&mut io.inputs);
         // defmt::info!(
         //     "{} {} {} {} {} {}",
@@ -0,0 +1,164 @@
+//! Settings and the pot calibration kept over power off in the last sector of
+//! the QSPI flash, which memory.x leaves out of the program.
+//!
+//! The flash can't be read while it is erased or programmed, so the synth core
+//! waits in RAM and the audio stops for a moment while saving. Saving is only
+//! done when a setting gesture ends or a calibration finishes.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use fixed::types::U24F8;
+use rp2040_hal::rom_data;
+use trumpet_synth::{
+    calibration::Calibration,
+    pitch::Cents,
+    settings::{Storage, Stored},
+};
+
+/// Size of the flash chip, see memory.x.
+const FLASH_SIZE: u32 = 2048 * 1024;
+const SECTOR_SIZE: u32 = 4096;
+const PAGE_SIZE: usize = 256;
+/// Offset of the reserved sector from the start of the flash.
+const STORAGE_OFFSET: u32 = FLASH_SIZE - SECTOR_SIZE;
+const XIP_BASE: u32 = 0x1000_0000;
+
+/// Block erase command of the flash chip and the size it erases.
+const SECTOR_ERASE: u8 = 0x20;
+
+/// Marks a sector holding settings, an erased sector reads all ones.
+const MAGIC: u32 = 0x5452_5054;
+/// Marks a page holding a calibration of the pots.
+const CALIBRATED: u8 = 1;
+const CALIBRATION_OFFSET: usize = 16;
+
+static PAUSE_SYNTH: AtomicBool = AtomicBool::new(false);
+static SYNTH_PAUSED: AtomicBool = AtomicBool::new(false);
+
+fn to_page(stored: Stored) -> [u8; PAGE_SIZE] {
+    let mut page = [0xff; PAGE_SIZE];
+    page[..4].copy_from_slice(&MAGIC.to_le_bytes());
+    page[4..8].copy_from_slice(&stored.reference_pitch.to_bits().to_le_bytes());
+    page[8..12].copy_from_slice(&stored.transpose.to_bits().to_le_bytes());
+    if let Some(calibration) = stored.calibration {
+        page[12] = CALIBRATED;
+        page[CALIBRATION_OFFSET..CALIBRATION_OFFSET + Calibration::SIZE]
+            .copy_from_slice(&calibration.to_bytes());
+    }
+    page
+}
+
+fn from_page(page: &[u8; PAGE_SIZE]) -> Option<Stored> {
+    let word = |at: usize| [page[at], page[at + 1], page[at + 2], page[at + 3]];
+
+    if u32::from_le_bytes(word(0)) != MAGIC {
+        return None;
+    }
+
+    // A corrupted calibration is dropped, the pots then read uncalibrated
+    let mut calibration = [0; Calibration::SIZE];
+    calibration.copy_from_slice(&page[CALIBRATION_OFFSET..CALIBRATION_OFFSET + Calibration::SIZE]);
+    let calibration = match page[12] {
+        CALIBRATED => Calibration::from_bytes(&calibration).ok(),
+        _ => None,
+    };
+
+    Some(Stored {
+        reference_pitch: U24F8::from_bits(u32::from_le_bytes(word(4))),
+        transpose: Cents::from_bits(i32::from_le_bytes(word(8))),
+        calibration,
+    })
+}
+
+/// The reserved flash sector. Saving must be done from the input core while
+/// the synth core calls `pause_point` regularly.
+pub struct FlashStorage;
+
+impl Storage for FlashStorage {
+    fn load(&mut self) -> Option<Stored> {
+        let mut page = [0; PAGE_SIZE];
+        let address = (XIP_BASE + STORAGE_OFFSET) as *const u8;
+        for (i, byte) in page.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile(address.add(i)) };
+        }
+
+        from_page(&page)
+    }
+
+    fn save(&mut self, stored: Stored) {
+        write(to_page(stored));
+    }
+}
+
+/// Erases the reserved sector and writes the page to it.
+fn write(page: [u8; PAGE_SIZE]) {
+    // The second stage bootloader sets up the fast flash access again after
+    // writing, it has to run from RAM as well
+    let mut boot2 = [0u32; 64];
+    for (i, word) in boot2.iter_mut().enumerate() {
+        *word = unsafe { core::ptr::read_volatile((XIP_BASE as *const u32).add(i)) };
+    }
+
+    let functions = FlashFunctions {
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+        flash_range_erase: rom_data::flash_range_erase::ptr(),
+        flash_range_program: rom_data::flash_range_program::ptr(),
+        flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+        boot2: boot2.as_ptr() as usize + 1,
+    };
+
+    PAUSE_SYNTH.store(true, Ordering::SeqCst);
+    while !SYNTH_PAUSED.load(Ordering::SeqCst) {}
+
+    cortex_m::interrupt::free(|_| unsafe {
+        write_sector(&functions, page.as_ptr());
+    });
+
+    PAUSE_SYNTH.store(false, Ordering::SeqCst);
+}
+
+/// Called by the synth core between buffers, waits in RAM while the flash
+/// is written.
+pub fn pause_point() {
+    if PAUSE_SYNTH.load(Ordering::SeqCst) {
+        cortex_m::interrupt::free(|_| unsafe { wait_in_ram() });
+    }
+}
+
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn wait_in_ram() {
+    SYNTH_PAUSED.store(true, Ordering::SeqCst);
+    while PAUSE_SYNTH.load(Ordering::SeqCst) {}
+    SYNTH_PAUSED.store(false, Ordering::SeqCst);
+}
+
+/// ROM functions looked up beforehand, the lookup itself runs from flash.
+struct FlashFunctions {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+    /// Thumb address of the copy of the second stage bootloader.
+    boot2: usize,
+}
+
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_sector(functions: &FlashFunctions, page: *const u8) {
+    (functions.connect_internal_flash)();
+    (functions.flash_exit_xip)();
+    (functions.flash_range_erase)(
+        STORAGE_OFFSET,
+        SECTOR_SIZE as usize,
+        SECTOR_SIZE,
+        SECTOR_ERASE,
+    );
+    (functions.flash_range_program)(STORAGE_OFFSET, page, PAGE_SIZE);
+    (functions.flash_flush_cache)();
+
+    let boot2: unsafe extern "C" fn() = core::mem::transmute(functions.boot2);
+    boot2();
+}
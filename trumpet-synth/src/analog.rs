@@ -0,0 +1,214 @@
+//! Conditioning of the analog inputs between the raw readings and the events
+//! sent to the trumpet. ADC readings are noisy: unfiltered they either send an
+//! event every update, or the threshold that silences them swallows the small
+//! movements of a bend. Every channel is smoothed and only reports once it
+//! moved further than its threshold from the value it reported last.
+
+use fixed::types::{I32F32, U0F16, U8F8};
+
+use crate::{
+    io::Millis,
+    trumpet::{Valve, MAX_VALVES},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Smoothing {
+    /// Readings are used as they are.
+    #[default]
+    None,
+    /// Exponential moving average, with the weight of every new reading. The
+    /// smoothing follows the number of readings rather than time.
+    Exponential(U0F16),
+    /// One euro filter: the cutoff frequency rises with the speed of the
+    /// input, so a pot at rest is smoothed heavily while a quick movement is
+    /// followed without lag.
+    OneEuro {
+        /// Cutoff in Hz at rest, lower removes more jitter.
+        min_cutoff: U8F8,
+        /// How much the cutoff rises per unit of travel per second, higher
+        /// lags less on fast movements.
+        beta: U8F8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelSettings {
+    pub smoothing: Smoothing,
+    /// How far the smoothed value has to move from the last reported value
+    /// before it is reported again.
+    pub threshold: U0F16,
+}
+
+impl ChannelSettings {
+    /// About the noise of a 12 bit ADC reading shifted to 16 bits.
+    pub const DEFAULT_THRESHOLD: U0F16 = U0F16::from_bits(20);
+
+    pub const DEFAULT: ChannelSettings = ChannelSettings {
+        smoothing: Smoothing::None,
+        threshold: Self::DEFAULT_THRESHOLD,
+    };
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The analog inputs that can be conditioned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalogChannel {
+    Embouchure,
+    Blowstrength,
+    Slide(Valve),
+    ValvePosition(Valve),
+    Vibrato,
+}
+
+/// Filter state and last reported value of a single channel.
+#[derive(Debug, Clone, Copy)]
+pub struct AnalogInput {
+    settings: ChannelSettings,
+    /// Filtered value and the time of the reading it was last updated with.
+    filtered: Option<(I32F32, Millis)>,
+    /// Smoothed speed of the input in units per second, for the one euro filter.
+    speed: I32F32,
+    reported: U0F16,
+}
+
+impl AnalogInput {
+    /// Cutoff in Hz of the smoothing of the speed in the one euro filter.
+    const SPEED_CUTOFF: I32F32 = I32F32::ONE;
+
+    /// Lowest cutoff in Hz, below it the time constant no longer fits.
+    const MIN_CUTOFF: I32F32 = I32F32::unwrapped_from_str("0.01");
+
+    const MILLIS_PER_SECOND: I32F32 = I32F32::unwrapped_from_str("1000");
+
+    pub fn new(settings: ChannelSettings) -> Self {
+        Self {
+            settings,
+            filtered: None,
+            speed: I32F32::ZERO,
+            reported: U0F16::ZERO,
+        }
+    }
+
+    pub fn settings(&self) -> &ChannelSettings {
+        &self.settings
+    }
+
+    /// Changes the conditioning, the filter starts over from the next reading.
+    pub fn set_settings(&mut self, settings: ChannelSettings) {
+        self.settings = settings;
+        self.filtered = None;
+        self.speed = I32F32::ZERO;
+    }
+
+    /// The value reported last.
+    pub fn value(&self) -> U0F16 {
+        self.reported
+    }
+
+    /// Filters a raw reading taken at `now`, returns the new value if it is
+    /// to be reported.
+    pub fn update(&mut self, reading: U0F16, now: Millis) -> Option<U0F16> {
+        let smoothed = U0F16::saturating_from_num(self.smooth(I32F32::from_num(reading), now));
+        if smoothed == self.reported {
+            return None;
+        }
+
+        // The threshold would keep the ends out of reach, those always report
+        let at_end = smoothed == U0F16::ZERO || smoothed == U0F16::MAX;
+        if smoothed.abs_diff(self.reported) <= self.settings.threshold && !at_end {
+            return None;
+        }
+
+        self.reported = smoothed;
+        Some(smoothed)
+    }
+
+    fn smooth(&mut self, reading: I32F32, now: Millis) -> I32F32 {
+        let Some((previous, time)) = self.filtered else {
+            self.filtered = Some((reading, now));
+            return reading;
+        };
+
+        let filtered = match self.settings.smoothing {
+            Smoothing::None => reading,
+            Smoothing::Exponential(weight) => {
+                previous + (reading - previous) * I32F32::from_num(weight)
+            }
+            Smoothing::OneEuro { min_cutoff, beta } => {
+                // Readings within the same millisecond carry no speed, the
+                // filter waits for time to pass
+                let elapsed = now.wrapping_sub(time);
+                if elapsed == 0 {
+                    return previous;
+                }
+
+                let speed =
+                    (reading - previous) * Self::MILLIS_PER_SECOND / I32F32::from_num(elapsed);
+                self.speed += (speed - self.speed) * Self::weight(Self::SPEED_CUTOFF, elapsed);
+
+                let cutoff = I32F32::from_num(min_cutoff)
+                    .saturating_add(I32F32::from_num(beta).saturating_mul(self.speed.abs()));
+                previous + (reading - previous) * Self::weight(cutoff, elapsed)
+            }
+        };
+
+        self.filtered = Some((filtered, now));
+        filtered
+    }
+
+    /// Weight of a new reading in a low pass filter with the given cutoff in
+    /// Hz, `elapsed` milliseconds after the previous reading.
+    fn weight(cutoff: I32F32, elapsed: Millis) -> I32F32 {
+        const TAU: I32F32 = I32F32::unwrapped_from_str("6.283185307");
+
+        let periods = TAU
+            .saturating_mul(cutoff.max(Self::MIN_CUTOFF))
+            .saturating_mul_int(elapsed as i64);
+        let time_constant = Self::MILLIS_PER_SECOND / periods;
+
+        I32F32::ONE / (I32F32::ONE + time_constant)
+    }
+}
+
+impl Default for AnalogInput {
+    fn default() -> Self {
+        Self::new(ChannelSettings::DEFAULT)
+    }
+}
+
+/// Conditioning of every analog input of the instrument.
+#[derive(Debug, Default, Clone)]
+pub struct AnalogInputs {
+    pub embouchure: AnalogInput,
+    pub blowstrength: AnalogInput,
+    pub slides: [AnalogInput; MAX_VALVES],
+    pub valve_positions: [AnalogInput; MAX_VALVES],
+    pub vibrato: AnalogInput,
+}
+
+impl AnalogInputs {
+    pub fn channel(&self, channel: AnalogChannel) -> &AnalogInput {
+        match channel {
+            AnalogChannel::Embouchure => &self.embouchure,
+            AnalogChannel::Blowstrength => &self.blowstrength,
+            AnalogChannel::Slide(valve) => &self.slides[valve as usize],
+            AnalogChannel::ValvePosition(valve) => &self.valve_positions[valve as usize],
+            AnalogChannel::Vibrato => &self.vibrato,
+        }
+    }
+
+    pub fn channel_mut(&mut self, channel: AnalogChannel) -> &mut AnalogInput {
+        match channel {
+            AnalogChannel::Embouchure => &mut self.embouchure,
+            AnalogChannel::Blowstrength => &mut self.blowstrength,
+            AnalogChannel::Slide(valve) => &mut self.slides[valve as usize],
+            AnalogChannel::ValvePosition(valve) => &mut self.valve_positions[valve as usize],
+            AnalogChannel::Vibrato => &mut self.vibrato,
+        }
+    }
+}
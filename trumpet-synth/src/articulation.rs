@@ -0,0 +1,149 @@
+//! Classifies how each note is started, so the synth can sound tongued and
+//! slurred notes differently.
+
+use fixed::types::U0F16;
+
+use crate::{
+    interface::TrumpetEvent,
+    io::Millis,
+    trumpet::{BlowStrength, ValveCombination},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Articulation {
+    /// First note of a phrase, after a rest longer than a tongued gap.
+    Attack,
+    /// Note restarted after a short break in the air stream.
+    Tongue,
+    /// Note pushed with clearly more air right after it started.
+    Accent,
+    /// Note changed by the valves without breaking the air stream.
+    Slur,
+    /// Partial changed by the embouchure without breaking the air stream, the
+    /// pitch glides over to the new partial.
+    LipSlur,
+}
+
+/// The valves and overtone of the note that is playing.
+pub type PlayedNote = (ValveCombination, u8);
+
+#[derive(Debug, Clone)]
+pub struct ArticulationDetector {
+    tongue_gap: Millis,
+    accent_threshold: BlowStrength,
+    /// When the last note stopped, None if no note was played yet or a note
+    /// is playing.
+    released: Option<Millis>,
+    /// When the note started and the lung pressure before it, while the note
+    /// can still turn out accented.
+    onset: Option<(Millis, BlowStrength)>,
+    /// Lung pressure of the last update.
+    lung_pressure: BlowStrength,
+}
+
+impl ArticulationDetector {
+    /// Longest break in the air stream that still counts as a tongued note.
+    pub const DEFAULT_TONGUE_GAP: Millis = 150;
+
+    /// How much the air has to rise after the onset to accent the note.
+    pub const DEFAULT_ACCENT_THRESHOLD: BlowStrength = U0F16::unwrapped_from_str("0.25");
+
+    /// How long after the onset a rise in air still accents the note.
+    pub const ACCENT_WINDOW: Millis = 50;
+
+    pub fn set_tongue_gap(&mut self, gap: Millis) {
+        self.tongue_gap = gap;
+    }
+
+    pub fn set_accent_threshold(&mut self, threshold: BlowStrength) {
+        self.accent_threshold = threshold;
+    }
+
+    /// Called once per update with the events of that update, the note
+    /// playing before and after them and the time of the update. An accent
+    /// is reported once the air rises, up to `ACCENT_WINDOW` after the onset.
+    pub fn update(
+        &mut self,
+        events: &[TrumpetEvent],
+        before: Option<PlayedNote>,
+        after: Option<PlayedNote>,
+        lung_pressure: BlowStrength,
+        now: Millis,
+    ) -> Option<Articulation> {
+        let onset = after.is_some()
+            && events
+                .iter()
+                .any(|event| matches!(event, TrumpetEvent::BlowDown));
+
+        if onset {
+            self.onset = Some((now, self.lung_pressure));
+        }
+        self.lung_pressure = lung_pressure;
+
+        let accented = match self.onset {
+            Some((start, pressure)) if after.is_some() => {
+                let within = now.wrapping_sub(start) <= Self::ACCENT_WINDOW;
+                if !within {
+                    self.onset = None;
+                }
+
+                within && lung_pressure > pressure.saturating_add(self.accent_threshold)
+            }
+            _ => false,
+        };
+        if accented {
+            self.onset = None;
+        }
+
+        let articulation = if accented {
+            Some(Articulation::Accent)
+        } else if onset {
+            // Released and blown again within this update is the shortest tongued gap
+            let gap = if before.is_some() {
+                Some(0)
+            } else {
+                self.released.map(|released| now.wrapping_sub(released))
+            };
+
+            if gap.is_some_and(|gap| gap <= self.tongue_gap) {
+                Some(Articulation::Tongue)
+            } else {
+                Some(Articulation::Attack)
+            }
+        } else if let (Some((_, overtone_before)), Some((_, overtone_after))) = (before, after) {
+            if overtone_before != overtone_after {
+                Some(Articulation::LipSlur)
+            } else if before != after {
+                Some(Articulation::Slur)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        match (before, after) {
+            (_, Some(_)) => self.released = None,
+            (Some(_), None) => {
+                self.released = Some(now);
+                self.onset = None;
+            }
+            (None, None) => (),
+        }
+
+        articulation
+    }
+}
+
+impl Default for ArticulationDetector {
+    fn default() -> Self {
+        Self {
+            tongue_gap: Self::DEFAULT_TONGUE_GAP,
+            accent_threshold: Self::DEFAULT_ACCENT_THRESHOLD,
+            released: None,
+            onset: None,
+            lung_pressure: BlowStrength::ZERO,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Pitch assist for players who do not have a trumpet embouchure: the lips
+//! hold on to their partial more firmly, bending is reduced and the result can
+//! be pulled onto the nearest equal tempered note.
+
+use fixed::types::{I32F32, U0F16, U24F8};
+
+use crate::{
+    pitch::{self, Cents, NotePitch},
+    trumpet::Embouchure,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PitchAssist {
+    /// 0 plays the raw physics, 1 plays every partial centered and in tune.
+    pub strength: U0F16,
+    /// Also pull the partials onto equal temperament, e.g. the flat seventh
+    /// partial, besides removing the bend.
+    pub quantize: bool,
+}
+
+impl PitchAssist {
+    /// How many times wider the slot hysteresis gets at full strength.
+    const HYSTERESIS_GAIN: I32F32 = I32F32::unwrapped_from_str("3");
+
+    /// Bend factor around 1 with the assist applied, full strength plays the
+    /// partial straight.
+    pub fn bend(&self, bend: U24F8) -> U24F8 {
+        if self.strength == U0F16::ZERO {
+            return bend;
+        }
+
+        let keep = I32F32::ONE - I32F32::from_num(self.strength);
+        let bend = I32F32::from_num(bend);
+
+        U24F8::saturating_from_num(I32F32::ONE + (bend - I32F32::ONE) * keep)
+    }
+
+    /// Slot hysteresis with the assist applied, so the embouchure has to move
+    /// further before the note cracks to another partial.
+    pub fn hysteresis(&self, hysteresis: Embouchure) -> Embouchure {
+        let gain = I32F32::ONE + I32F32::from_num(self.strength) * Self::HYSTERESIS_GAIN;
+
+        Embouchure::saturating_from_num(I32F32::from_num(hysteresis) * gain)
+    }
+
+    /// Pulls a frequency towards the nearest equal tempered note relative to
+    /// the reference pitch, if quantizing.
+    pub fn quantize(&self, frequency: U24F8, reference: U24F8) -> U24F8 {
+        if !self.quantize || self.strength == U0F16::ZERO {
+            return frequency;
+        }
+
+        let Some(nearest) = NotePitch::nearest_with_reference(frequency, reference, 0) else {
+            return frequency;
+        };
+
+        let correction = -I32F32::from_num(nearest.cents) * I32F32::from_num(self.strength);
+        pitch::scale(
+            frequency,
+            pitch::cents_to_ratio(Cents::saturating_from_num(correction)),
+        )
+    }
+}
@@ -0,0 +1,216 @@
+//! Calibration of the potentiometers. Pots rarely travel their full range, and
+//! one that tops out below the highest slot in the embouchure map can never
+//! play the top partials. The calibration stretches the travel that is there
+//! over the full range, with dead zones so the ends and the resting position
+//! read steadily.
+
+use fixed::types::{I32F32, U0F16};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationError {
+    /// A pot moved less than `PotCalibration::MIN_RANGE` during the sweep.
+    RangeTooSmall,
+    /// The readings are not ordered min, center, max, or the dead zones
+    /// cover the whole range.
+    Invalid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PotCalibration {
+    /// Lowest reading of the pot.
+    pub min: U0F16,
+    /// Reading of the pot at rest.
+    pub center: U0F16,
+    /// Highest reading of the pot.
+    pub max: U0F16,
+    /// Readings this close to the ends or the resting position snap to them.
+    pub dead_zone: U0F16,
+}
+
+impl PotCalibration {
+    /// Passes readings through unchanged.
+    pub const IDENTITY: PotCalibration = PotCalibration {
+        min: U0F16::ZERO,
+        center: U0F16::ZERO,
+        max: U0F16::MAX,
+        dead_zone: U0F16::ZERO,
+    };
+
+    /// Roughly ten times the noise of a pot read by a 12 bit ADC.
+    pub const DEFAULT_DEAD_ZONE: U0F16 = U0F16::unwrapped_from_str("0.02");
+
+    /// Least travel a sweep has to cover, less means the pot was not swept.
+    pub const MIN_RANGE: U0F16 = U0F16::unwrapped_from_str("0.25");
+
+    /// Validates and builds a calibration from the readings of a sweep.
+    pub fn new(
+        min: U0F16,
+        center: U0F16,
+        max: U0F16,
+        dead_zone: U0F16,
+    ) -> Result<Self, CalibrationError> {
+        let calibration = Self {
+            min,
+            center,
+            max,
+            dead_zone,
+        };
+        if max.saturating_sub(min) < Self::MIN_RANGE {
+            return Err(CalibrationError::RangeTooSmall);
+        }
+        calibration.validate()?;
+
+        Ok(calibration)
+    }
+
+    fn validate(&self) -> Result<(), CalibrationError> {
+        if self.min > self.center || self.center > self.max {
+            return Err(CalibrationError::Invalid);
+        }
+
+        if self.max - self.min <= self.dead_zone.saturating_mul_int(2) {
+            return Err(CalibrationError::Invalid);
+        }
+
+        Ok(())
+    }
+
+    /// Normalizes a raw reading onto the full range.
+    pub fn apply(&self, reading: U0F16) -> U0F16 {
+        let low = self.min.saturating_add(self.dead_zone);
+        let high = self.max.saturating_sub(self.dead_zone);
+        if high <= low {
+            return reading;
+        }
+
+        let reading = if reading.abs_diff(self.center) < self.dead_zone {
+            self.center
+        } else {
+            reading
+        };
+
+        if reading <= low {
+            return U0F16::ZERO;
+        }
+        if reading >= high {
+            return U0F16::MAX;
+        }
+
+        U0F16::saturating_from_num(I32F32::from_num(reading - low) / I32F32::from_num(high - low))
+    }
+
+    fn to_bytes(self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        let fields = [self.min, self.center, self.max, self.dead_zone];
+        for (chunk, field) in bytes.chunks_exact_mut(2).zip(fields) {
+            chunk.copy_from_slice(&field.to_bits().to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CalibrationError> {
+        let field = |index: usize| {
+            U0F16::from_bits(u16::from_le_bytes([bytes[index * 2], bytes[index * 2 + 1]]))
+        };
+
+        let calibration = Self {
+            min: field(0),
+            center: field(1),
+            max: field(2),
+            dead_zone: field(3),
+        };
+        calibration.validate()?;
+
+        Ok(calibration)
+    }
+}
+
+impl Default for PotCalibration {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Calibration of both pots of the instrument.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration {
+    pub embouchure: PotCalibration,
+    pub blowstrength: PotCalibration,
+}
+
+impl Calibration {
+    /// Size of the calibration in bytes, for persisting it.
+    pub const SIZE: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.embouchure.to_bytes());
+        bytes[8..].copy_from_slice(&self.blowstrength.to_bytes());
+        bytes
+    }
+
+    /// Reads back a persisted calibration, rejecting corrupted data.
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Result<Self, CalibrationError> {
+        Ok(Self {
+            embouchure: PotCalibration::from_bytes(&bytes[..8])?,
+            blowstrength: PotCalibration::from_bytes(&bytes[8..])?,
+        })
+    }
+}
+
+/// Travel of a single pot seen during a sweep.
+#[derive(Debug, Default, Clone, Copy)]
+struct PotSweep {
+    center: Option<U0F16>,
+    min: U0F16,
+    max: U0F16,
+}
+
+impl PotSweep {
+    fn update(&mut self, reading: U0F16) {
+        if self.center.is_none() {
+            self.center = Some(reading);
+            self.min = reading;
+            self.max = reading;
+        }
+
+        self.min = self.min.min(reading);
+        self.max = self.max.max(reading);
+    }
+
+    fn finish(&self) -> Result<PotCalibration, CalibrationError> {
+        let center = self.center.ok_or(CalibrationError::RangeTooSmall)?;
+
+        PotCalibration::new(
+            self.min,
+            center,
+            self.max,
+            PotCalibration::DEFAULT_DEAD_ZONE,
+        )
+    }
+}
+
+/// A calibration in progress. The first readings are taken as the resting
+/// positions of the pots, after that the player sweeps both pots through
+/// their full travel.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CalibrationRoutine {
+    embouchure: PotSweep,
+    blowstrength: PotSweep,
+}
+
+impl CalibrationRoutine {
+    /// Called with the raw readings of every update during the sweep.
+    pub fn update(&mut self, embouchure: U0F16, blowstrength: U0F16) {
+        self.embouchure.update(embouchure);
+        self.blowstrength.update(blowstrength);
+    }
+
+    pub fn finish(&self) -> Result<Calibration, CalibrationError> {
+        Ok(Calibration {
+            embouchure: self.embouchure.finish()?,
+            blowstrength: self.blowstrength.finish()?,
+        })
+    }
+}
@@ -0,0 +1,42 @@
+//! Debouncing of the buttons on time rather than on updates, so a button
+//! behaves the same whether the inputs are read in a tight loop or every 10ms.
+
+use crate::io::Millis;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Debouncer {
+    debounce_time: Millis,
+    /// The last reading and since when the button reads it.
+    reading: Option<(bool, Millis)>,
+    stable: Option<bool>,
+}
+
+impl Debouncer {
+    pub fn new(debounce_time: Millis) -> Self {
+        Self {
+            debounce_time,
+            reading: None,
+            stable: None,
+        }
+    }
+
+    /// A reading becomes the stable state once the button read it for the
+    /// debounce time.
+    pub fn update(&mut self, reading: bool, now: Millis) {
+        let since = match self.reading {
+            Some((last, since)) if last == reading => since,
+            _ => now,
+        };
+        self.reading = Some((reading, since));
+
+        if now.wrapping_sub(since) >= self.debounce_time {
+            self.stable = Some(reading);
+        }
+    }
+
+    /// The stable state, None until the button read the same for the
+    /// debounce time once.
+    pub fn is_high(&self) -> Option<bool> {
+        self.stable
+    }
+}
@@ -0,0 +1,114 @@
+//! Response curves from the raw blowstrength reading to how loud the horn
+//! plays and how far the lungs can bend a note. Breath sensors differ wildly
+//! in how their readings relate to effort, and players differ in how much of
+//! the range they comfortably use.
+
+use fixed::types::{I32F32, U0F16, U8F8};
+use heapless::Vec;
+
+use crate::{
+    pitch::{self, Cents},
+    trumpet::BlowStrength,
+};
+
+/// Upper bound on the amount of points in a custom curve.
+pub const MAX_CURVE_POINTS: usize = 8;
+
+/// Cents per decibel of amplitude, 1200 / (20 * log10(2)).
+const CENTS_PER_DECIBEL: I32F32 = I32F32::unwrapped_from_str("199.315685693");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CurveError {
+    /// The curve contains no points.
+    Empty,
+    /// More than `MAX_CURVE_POINTS` points were given.
+    TooManyPoints,
+    /// The blowstrengths of the points are not in ascending order.
+    NotMonotonic,
+}
+
+/// Maps a blowstrength from 0 to 1 onto a response from 0 to 1.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum ResponseCurve {
+    /// The response follows the reading directly.
+    #[default]
+    Linear,
+    /// Barely responds to soft playing and steepens towards full strength:
+    /// (2^(s*x) - 1) / (2^s - 1), with the steepness s in octaves up to 15.
+    Exponential(U8F8),
+    /// Spreads the reading evenly over a range in decibels below full
+    /// strength, so equal steps of effort sound like equal steps in loudness.
+    Decibels(U8F8),
+    /// Interpolates linearly between measured points, e.g. to straighten out
+    /// a particular breath sensor. Readings outside the points are clamped.
+    Piecewise(Vec<(BlowStrength, U0F16), MAX_CURVE_POINTS>),
+}
+
+impl ResponseCurve {
+    /// Validates and builds a custom curve, the points ascending in blowstrength.
+    pub fn piecewise(points: &[(BlowStrength, U0F16)]) -> Result<Self, CurveError> {
+        if points.is_empty() {
+            return Err(CurveError::Empty);
+        }
+
+        if points.windows(2).any(|points| points[0].0 > points[1].0) {
+            return Err(CurveError::NotMonotonic);
+        }
+
+        Ok(ResponseCurve::Piecewise(
+            Vec::from_slice(points).map_err(|_| CurveError::TooManyPoints)?,
+        ))
+    }
+
+    pub fn apply(&self, blowstrength: BlowStrength) -> U0F16 {
+        let x = I32F32::from_num(blowstrength);
+
+        match self {
+            ResponseCurve::Linear => blowstrength,
+            ResponseCurve::Exponential(steepness) => {
+                if *steepness == U8F8::ZERO {
+                    return blowstrength;
+                }
+
+                // Beyond 15 octaves 2^s no longer fits a ratio
+                let steepness = I32F32::from_num(*steepness).min(I32F32::from_num(15));
+                let rise = exp2(steepness * x) - I32F32::ONE;
+                let full = exp2(steepness) - I32F32::ONE;
+
+                U0F16::saturating_from_num(rise / full)
+            }
+            ResponseCurve::Decibels(range) => {
+                if blowstrength == BlowStrength::ZERO {
+                    return U0F16::ZERO;
+                }
+
+                let decibels = (x - I32F32::ONE) * I32F32::from_num(*range);
+                let cents = Cents::saturating_from_num(decibels * CENTS_PER_DECIBEL);
+
+                U0F16::saturating_from_num(pitch::cents_to_ratio(cents))
+            }
+            ResponseCurve::Piecewise(points) => {
+                let Some(upper) = points.iter().position(|&(x, _)| x >= blowstrength) else {
+                    return points.last().map_or(blowstrength, |&(_, y)| y);
+                };
+
+                if upper == 0 {
+                    return points[0].1;
+                }
+
+                let (x0, y0) = points[upper - 1];
+                let (x1, y1) = points[upper];
+                let progress = I32F32::from_num(blowstrength - x0) / I32F32::from_num(x1 - x0);
+                let (y0, y1) = (I32F32::from_num(y0), I32F32::from_num(y1));
+
+                U0F16::saturating_from_num(y0 + (y1 - y0) * progress)
+            }
+        }
+    }
+}
+
+/// 2^x for the small positive exponents of the curves.
+fn exp2(x: I32F32) -> I32F32 {
+    I32F32::from_num(pitch::cents_to_ratio(Cents::saturating_from_num(x * 1200)))
+}
@@ -0,0 +1,373 @@
+//! Jazz effects that are played on top of a note: shakes, doits, falls and
+//! flutter tongue. They are triggered by gestures on the valves, or directly
+//! by a front-end, and shape the pitch and volume over the following time.
+
+use fixed::types::{I32F32, U0F16, U8F8};
+use heapless::Vec;
+
+use crate::{
+    articulation::PlayedNote,
+    interface::TrumpetEvent,
+    io::Millis,
+    pitch::{self, Cents, Ratio},
+    trumpet::{Valve, ValveCombination, MAX_VALVES},
+};
+
+const MILLIS_PER_SECOND: I32F32 = I32F32::unwrapped_from_str("1000");
+
+/// Upper bound on the amount of gestures bound at the same time.
+pub const MAX_BINDINGS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Effect {
+    /// Lip trill between the note and the partial above it.
+    Shake,
+    /// Quick rise in pitch that fades out at the top.
+    Doit,
+    /// Drop in pitch that fades out at the bottom.
+    Fall,
+    /// Rolled tongue, a fast flutter in the volume.
+    Flutter,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 4] = [Effect::Shake, Effect::Doit, Effect::Fall, Effect::Flutter];
+
+    /// Sustained effects last until the note ends, the others end the note.
+    pub fn sustained(self) -> bool {
+        matches!(self, Effect::Shake | Effect::Flutter)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectParameters {
+    /// Shakes and flutters per second, or for doits and falls the inverse of
+    /// how long they take.
+    pub speed: U8F8,
+    /// How much of the partial above a shake reaches, how many octaves a
+    /// doit or fall covers, or how deep a flutter cuts the volume.
+    pub depth: U0F16,
+}
+
+impl EffectParameters {
+    pub const DEFAULT: [EffectParameters; 4] = [
+        // Shake
+        EffectParameters {
+            speed: U8F8::unwrapped_from_str("7"),
+            depth: U0F16::MAX,
+        },
+        // Doit
+        EffectParameters {
+            speed: U8F8::unwrapped_from_str("3"),
+            depth: U0F16::unwrapped_from_str("0.75"),
+        },
+        // Fall
+        EffectParameters {
+            speed: U8F8::unwrapped_from_str("2"),
+            depth: U0F16::MAX,
+        },
+        // Flutter
+        EffectParameters {
+            speed: U8F8::unwrapped_from_str("25"),
+            depth: U0F16::unwrapped_from_str("0.8"),
+        },
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    /// The valve pressed three times in quick succession while playing.
+    ValveFlutter(Valve),
+    /// Holding exactly this valve combination while playing. The valves
+    /// still finger the note, unless the definition has fewer valves than the
+    /// front-end reads. Sustained effects stop when the combination is
+    /// released.
+    Combination(ValveCombination),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectBinding {
+    pub gesture: Gesture,
+    pub effect: Effect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EffectError {
+    /// More than `MAX_BINDINGS` gestures were bound.
+    TooManyBindings,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActiveEffect {
+    effect: Effect,
+    /// When the effect started, None until the update after a trigger.
+    started: Option<Millis>,
+    /// Time since the effect started as of the last update.
+    elapsed: Millis,
+    gesture: Option<Gesture>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ValvePresses {
+    count: u8,
+    /// Time of the first press that is still counted.
+    first: Millis,
+}
+
+#[derive(Debug, Clone)]
+pub struct Effects {
+    bindings: Vec<EffectBinding, MAX_BINDINGS>,
+    parameters: [EffectParameters; 4],
+    active: Option<ActiveEffect>,
+    /// A doit or fall ran out, the note stays silent until it is blown again.
+    finished: bool,
+    presses: [ValvePresses; MAX_VALVES],
+    combination: Option<ValveCombination>,
+}
+
+impl Effects {
+    /// Presses within `FLUTTER_WINDOW` that make a valve flutter.
+    const FLUTTER_PRESSES: u8 = 3;
+    const FLUTTER_WINDOW: Millis = 300;
+
+    /// Bound until the front-end binds its own, playable on three valves.
+    /// Flutter tongue is left for front-ends to trigger directly.
+    pub const DEFAULT_BINDINGS: [EffectBinding; 3] = [
+        EffectBinding {
+            gesture: Gesture::ValveFlutter(Valve::First),
+            effect: Effect::Shake,
+        },
+        EffectBinding {
+            gesture: Gesture::ValveFlutter(Valve::Second),
+            effect: Effect::Fall,
+        },
+        EffectBinding {
+            gesture: Gesture::ValveFlutter(Valve::Third),
+            effect: Effect::Doit,
+        },
+    ];
+
+    pub fn bind(&mut self, gesture: Gesture, effect: Effect) -> Result<(), EffectError> {
+        self.bindings
+            .push(EffectBinding { gesture, effect })
+            .map_err(|_| EffectError::TooManyBindings)
+    }
+
+    pub fn unbind_all(&mut self) {
+        self.bindings.clear();
+    }
+
+    pub fn bindings(&self) -> &[EffectBinding] {
+        &self.bindings
+    }
+
+    pub fn set_parameters(&mut self, effect: Effect, parameters: EffectParameters) {
+        self.parameters[effect as usize] = parameters;
+    }
+
+    pub fn parameters(&self, effect: Effect) -> EffectParameters {
+        self.parameters[effect as usize]
+    }
+
+    /// Starts an effect on the note that is playing, e.g. from a front-end.
+    pub fn trigger(&mut self, effect: Effect) {
+        self.start(effect, None);
+    }
+
+    /// The effect that is playing, if any.
+    pub fn active(&self) -> Option<Effect> {
+        self.active.map(|active| active.effect)
+    }
+
+    fn start(&mut self, effect: Effect, gesture: Option<Gesture>) {
+        self.finished = false;
+        self.active = Some(ActiveEffect {
+            effect,
+            started: None,
+            elapsed: 0,
+            gesture,
+        });
+    }
+
+    /// Called once per update with the events of that update, the note
+    /// playing after them and the time of the update. Returns whether the
+    /// effects changed the pitch or volume of the note.
+    pub fn update(
+        &mut self,
+        events: &[TrumpetEvent],
+        note: Option<PlayedNote>,
+        now: Millis,
+    ) -> bool {
+        let Some((combination, _)) = note else {
+            let changed = self.active.is_some() || self.finished;
+            self.active = None;
+            self.finished = false;
+            self.presses = Default::default();
+            self.combination = None;
+            return changed;
+        };
+
+        let mut changed = self
+            .active
+            .is_some_and(|active| active.effect != Effect::Flutter);
+
+        if events
+            .iter()
+            .any(|event| matches!(event, TrumpetEvent::BlowDown))
+        {
+            changed |= self.finished;
+            self.active = None;
+            self.finished = false;
+        }
+
+        for presses in self.presses.iter_mut() {
+            if now.wrapping_sub(presses.first) > Self::FLUTTER_WINDOW {
+                *presses = ValvePresses::default();
+            }
+        }
+
+        let mut fluttered = [false; MAX_VALVES];
+        for event in events {
+            if let TrumpetEvent::ValveDown(valve) = event {
+                let presses = &mut self.presses[*valve as usize];
+                if presses.count == 0 {
+                    presses.first = now;
+                }
+                presses.count += 1;
+
+                if presses.count >= Self::FLUTTER_PRESSES {
+                    fluttered[*valve as usize] = true;
+                    *presses = ValvePresses::default();
+                }
+            }
+        }
+
+        let previous_combination = self.combination.replace(combination);
+
+        for binding in self.bindings.clone() {
+            let started = match binding.gesture {
+                Gesture::ValveFlutter(valve) => fluttered[valve as usize],
+                Gesture::Combination(held) => {
+                    let pressed = combination == held && previous_combination != Some(held);
+                    let released = combination != held && previous_combination == Some(held);
+
+                    let sustained_by_this = self
+                        .active
+                        .is_some_and(|active| active.gesture == Some(binding.gesture));
+                    if released && sustained_by_this && binding.effect.sustained() {
+                        self.active = None;
+                        changed = true;
+                    }
+
+                    pressed
+                }
+            };
+
+            if started {
+                self.start(binding.effect, Some(binding.gesture));
+                changed = true;
+            }
+        }
+
+        if let Some(mut active) = self.active {
+            let started = *active.started.get_or_insert(now);
+            active.elapsed = now.wrapping_sub(started);
+            self.active = Some(active);
+
+            if !active.effect.sustained() && self.progress(&active) >= I32F32::ONE {
+                self.active = None;
+                self.finished = true;
+            }
+        }
+
+        changed
+    }
+
+    /// How far a doit or fall is, from 0 to 1.
+    fn progress(&self, active: &ActiveEffect) -> I32F32 {
+        let speed = I32F32::from_num(self.parameters(active.effect).speed);
+        let seconds = I32F32::saturating_from_num(active.elapsed) / MILLIS_PER_SECOND;
+
+        seconds.saturating_mul(speed).min(I32F32::ONE)
+    }
+
+    /// Frequency factor the effect applies to a note on the given overtone.
+    pub fn pitch_ratio(&self, overtone: u8) -> Ratio {
+        let Some(active) = &self.active else {
+            return Ratio::ONE;
+        };
+
+        let parameters = self.parameters(active.effect);
+        let depth = I32F32::from_num(parameters.depth);
+
+        match active.effect {
+            Effect::Shake => {
+                // Only the parity counts, so the count may wrap on long shakes
+                let rate = I32F32::from_num(parameters.speed) * 2 / MILLIS_PER_SECOND;
+                let half_periods = I32F32::wrapping_from_num(active.elapsed).wrapping_mul(rate);
+                if half_periods.to_bits() >> I32F32::FRAC_NBITS & 1 == 0 {
+                    return Ratio::ONE;
+                }
+
+                // Frequency = fundamental * (overtone + 1)
+                let step = I32F32::ONE / I32F32::from_num(overtone as u32 + 1);
+                Ratio::saturating_from_num(I32F32::ONE + step * depth)
+            }
+            Effect::Doit | Effect::Fall => {
+                let progress = self.progress(active);
+                let octaves = depth * progress * progress;
+                let cents = Cents::saturating_from_num(octaves * 1200);
+
+                pitch::cents_to_ratio(if active.effect == Effect::Fall {
+                    -cents
+                } else {
+                    cents
+                })
+            }
+            Effect::Flutter => Ratio::ONE,
+        }
+    }
+
+    /// Volume factor of the effect, doits and falls fade the note out.
+    pub fn volume(&self) -> U0F16 {
+        if self.finished {
+            return U0F16::ZERO;
+        }
+
+        let Some(active) = &self.active else {
+            return U0F16::MAX;
+        };
+
+        let progress = self.progress(active);
+        match active.effect {
+            Effect::Doit => U0F16::saturating_from_num(I32F32::ONE - progress * progress),
+            Effect::Fall => U0F16::saturating_from_num(I32F32::ONE - progress),
+            Effect::Shake | Effect::Flutter => U0F16::MAX,
+        }
+    }
+
+    /// Speed and depth of a flutter tongue that is playing.
+    pub fn flutter(&self) -> Option<EffectParameters> {
+        match self.active {
+            Some(active) if active.effect == Effect::Flutter => {
+                Some(self.parameters(Effect::Flutter))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self {
+            bindings: Self::DEFAULT_BINDINGS.iter().copied().collect(),
+            parameters: EffectParameters::DEFAULT,
+            active: None,
+            finished: false,
+            presses: Default::default(),
+            combination: None,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! The air the instrument is played in. Its temperature sets the speed of
+//! sound, and with it the tuning of the whole instrument: a cold horn plays
+//! flat, a warm one sharp.
+
+use fixed::types::{I16F16, I32F32, U0F16, U24F8};
+
+/// Air temperature in degrees Celsius.
+pub type Celsius = I16F16;
+
+/// Relative humidity, from dry (0) to saturated (~1).
+pub type Humidity = U0F16;
+
+/// Speed of sound in dry air at 0°C, in mm/s.
+const SPEED_OF_SOUND_AT_FREEZING: I32F32 = I32F32::unwrapped_from_str("331300");
+
+/// Increase of the speed of sound per degree, in mm/s.
+const SPEED_PER_DEGREE: I32F32 = I32F32::unwrapped_from_str("606");
+
+/// Increase of the speed of sound in saturated air over dry air at room
+/// temperature, in mm/s.
+const SPEED_FROM_HUMIDITY: I32F32 = I32F32::unwrapped_from_str("1240");
+
+/// Ambient conditions of the air inside the instrument. Air pressure, and
+/// with it altitude, does not change the speed of sound; playing high up only
+/// matters through the colder air, which the temperature accounts for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Environment {
+    pub temperature: Celsius,
+    pub humidity: Humidity,
+}
+
+impl Environment {
+    /// Room temperature, the climate the instrument definitions are measured in.
+    pub const ROOM: Environment = Environment {
+        temperature: Celsius::unwrapped_from_str("20"),
+        humidity: Humidity::ZERO,
+    };
+
+    /// Speed of sound in mm/s, linearized around room temperature which is
+    /// accurate to within 0.1% over the range instruments are played in.
+    pub fn speed_of_sound(&self) -> U24F8 {
+        let speed = SPEED_OF_SOUND_AT_FREEZING
+            + I32F32::from_num(self.temperature) * SPEED_PER_DEGREE
+            + I32F32::from_num(self.humidity) * SPEED_FROM_HUMIDITY;
+
+        U24F8::saturating_from_num(speed)
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::ROOM
+    }
+}
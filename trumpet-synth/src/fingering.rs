@@ -0,0 +1,168 @@
+//! Fingering charts derived from the tubing of an instrument: every valve
+//! combination is combined with every overtone the tuning can play, and the
+//! resulting pitches are grouped per written note.
+
+use core::fmt;
+
+use heapless::Vec;
+
+use crate::{
+    environment::Environment,
+    pitch::{self, Cents, NotePitch},
+    trumpet::{TrumpetDefinition, ValveCombination, MAX_VALVES},
+    tuning::{TrumpetTuning, MAX_OVERTONES},
+};
+
+/// Upper bound on the amount of fingerings listed for a single note.
+pub const MAX_FINGERINGS: usize = 8;
+
+/// Upper bound on the amount of notes in a chart, enough for the full range
+/// of a 16 overtone instrument.
+pub const MAX_CHART_NOTES: usize = 64;
+
+/// One way to play a note: a valve combination and the overtone to lip.
+#[derive(Debug, Clone, Copy)]
+pub struct Fingering {
+    pub valves: ValveCombination,
+    pub overtone: u8,
+    /// How far the unbent overtone lies from the note.
+    pub cents: Cents,
+}
+
+impl Fingering {
+    /// The partial as players count it, with the pedal as the 1st: overtone 0
+    /// is the 2nd partial, written C5 the 4th and G5 the 6th.
+    pub fn partial(&self) -> u32 {
+        2 * (self.overtone as u32 + 1)
+    }
+}
+
+impl fmt::Display for Fingering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.valves == 0 {
+            write!(f, "0")?;
+        }
+
+        for valve in 0..MAX_VALVES {
+            if self.valves & (1 << valve) != 0 {
+                write!(f, "{}", valve + 1)?;
+            }
+        }
+
+        write!(
+            f,
+            " (partial {}, {:+})",
+            self.partial(),
+            self.cents.round().to_num::<i32>()
+        )
+    }
+}
+
+/// All fingerings for one written note, the primary fingering first.
+#[derive(Debug, Clone)]
+pub struct ChartNote {
+    /// Midi note number of the written note.
+    pub midi: i32,
+    pub fingerings: Vec<Fingering, MAX_FINGERINGS>,
+}
+
+impl ChartNote {
+    /// The fingering closest in tune, preferring fewer valves on a tie.
+    pub fn primary(&self) -> Option<&Fingering> {
+        self.fingerings.first()
+    }
+
+    pub fn alternates(&self) -> &[Fingering] {
+        self.fingerings.get(1..).unwrap_or(&[])
+    }
+
+    fn insert(&mut self, fingering: Fingering) {
+        let key = |fingering: &Fingering| (fingering.cents.abs(), fingering.valves.count_ones());
+
+        let position = self
+            .fingerings
+            .iter()
+            .position(|other| key(&fingering) < key(other))
+            .unwrap_or(self.fingerings.len());
+
+        if self.fingerings.is_full() {
+            if position == self.fingerings.len() {
+                return;
+            }
+            self.fingerings.pop();
+        }
+
+        // Cannot fail, space was made above
+        self.fingerings.insert(position, fingering).ok();
+    }
+}
+
+impl fmt::Display for ChartNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        pitch::write_midi_name(f, self.midi)?;
+
+        for (i, fingering) in self.fingerings.iter().enumerate() {
+            let separator = if i == 0 { ":" } else { "," };
+            write!(f, "{} {}", separator, fingering)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every fingering of the instrument at room temperature, grouped per written
+/// note in ascending order. Overtones the embouchure map cannot reach are left
+/// out.
+pub fn chart(def: &TrumpetDefinition, tuning: &TrumpetTuning) -> Vec<ChartNote, MAX_CHART_NOTES> {
+    let mut chart: Vec<ChartNote, MAX_CHART_NOTES> = Vec::new();
+    let speed_of_sound = Environment::ROOM.speed_of_sound();
+
+    let map = tuning.embouchure_to_overtone_map();
+    let overtones = tuning.overtones().min(MAX_OVERTONES) as u8;
+    for overtone in 0..overtones {
+        if map.get(overtone as usize + 1) == Some(&map[overtone as usize]) {
+            continue;
+        }
+
+        for valves in 0..=def.valve_mask() {
+            let frequency = def.harmonic(def.tube_length(valves), overtone, speed_of_sound);
+            let Some(pitch) = NotePitch::nearest(frequency, def.transposition) else {
+                continue;
+            };
+
+            let fingering = Fingering {
+                valves,
+                overtone,
+                cents: pitch.cents,
+            };
+
+            match chart.binary_search_by_key(&pitch.midi, |note| note.midi) {
+                Ok(index) => chart[index].insert(fingering),
+                Err(index) => {
+                    let mut note = ChartNote {
+                        midi: pitch.midi,
+                        fingerings: Vec::new(),
+                    };
+                    note.insert(fingering);
+
+                    // Notes beyond the chart size are dropped, the range is
+                    // far wider than any instrument plays
+                    chart.insert(index, note).ok();
+                }
+            }
+        }
+    }
+
+    chart
+}
+
+/// All fingerings for a written note, e.g. "how do I play G5 on this horn?".
+pub fn fingerings(
+    def: &TrumpetDefinition,
+    tuning: &TrumpetTuning,
+    written_midi: i32,
+) -> Option<ChartNote> {
+    chart(def, tuning)
+        .into_iter()
+        .find(|note| note.midi == written_midi)
+}
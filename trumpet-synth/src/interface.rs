@@ -1,10 +1,20 @@
-use common::debouncer::Debouncer;
-use fixed::types::U0F16;
+use fixed::types::{I32F32, U0F16};
 use heapless::Vec;
+use rytmos_synth::commands::{Command, CommandMessage};
 
 use crate::{
-    io::{Fifo, Inputs, TrumpetInputState, IO},
-    trumpet::{BlowStrength, Embouchure, Trumpet, Valve, BFLAT_TRUMPET},
+    analog::{AnalogChannel, AnalogInputs, ChannelSettings},
+    calibration::{Calibration, CalibrationError, CalibrationRoutine},
+    debounce::Debouncer,
+    environment::Celsius,
+    io::{Clock, Fifo, Input, InputError, Inputs, Millis, TrumpetInputState, IO, MAX_INPUTS},
+    presets::TrumpetPreset,
+    synth::TrumpetSynthCommand,
+    trumpet::{
+        BlowStrength, Embouchure, SlideExtension, Trumpet, TrumpetDefinition, TrumpetSetting,
+        Valve, ValvePosition, BFLAT_TRUMPET, MAX_COMMANDS, MAX_VALVES,
+    },
+    tuning::TrumpetTuning,
 };
 
 // TODO: different place for some of these structs/impls?
@@ -17,6 +27,29 @@ pub enum TrumpetEvent {
     ValveDown(Valve),
     EmbouchureChange(Embouchure),
     BlowStrengthChange(BlowStrength),
+    SlideChange(Valve, SlideExtension),
+    ValvePositionChange(Valve, ValvePosition),
+    TemperatureChange(Celsius),
+    VibratoChange(U0F16),
+}
+
+impl TrumpetEvent {
+    /// Whether both events change the same analog input, so the later one
+    /// makes the earlier one redundant.
+    pub fn supersedes(&self, other: &TrumpetEvent) -> bool {
+        match (self, other) {
+            (TrumpetEvent::EmbouchureChange(_), TrumpetEvent::EmbouchureChange(_))
+            | (TrumpetEvent::BlowStrengthChange(_), TrumpetEvent::BlowStrengthChange(_))
+            | (TrumpetEvent::TemperatureChange(_), TrumpetEvent::TemperatureChange(_))
+            | (TrumpetEvent::VibratoChange(_), TrumpetEvent::VibratoChange(_)) => true,
+            (TrumpetEvent::SlideChange(valve, _), TrumpetEvent::SlideChange(other_valve, _))
+            | (
+                TrumpetEvent::ValvePositionChange(valve, _),
+                TrumpetEvent::ValvePositionChange(other_valve, _),
+            ) => valve == other_valve,
+            _ => false,
+        }
+    }
 }
 
 #[cfg(feature = "defmt")]
@@ -41,86 +74,363 @@ impl defmt::Format for TrumpetEvent {
             TrumpetEvent::BlowStrengthChange(b) => {
                 defmt::write!(fmt, "TrumpetEvent::BlowStrengthChange({:?})", b.to_bits())
             }
+            TrumpetEvent::SlideChange(v, s) => {
+                defmt::write!(
+                    fmt,
+                    "TrumpetEvent::SlideChange({:?}, {:?})",
+                    Into::<usize>::into(*v),
+                    s.to_bits()
+                )
+            }
+            TrumpetEvent::ValvePositionChange(v, p) => {
+                defmt::write!(
+                    fmt,
+                    "TrumpetEvent::ValvePositionChange({:?}, {:?})",
+                    Into::<usize>::into(*v),
+                    p.to_bits()
+                )
+            }
+            TrumpetEvent::TemperatureChange(t) => {
+                defmt::write!(fmt, "TrumpetEvent::TemperatureChange({:?})", t.to_bits())
+            }
+            TrumpetEvent::VibratoChange(v) => {
+                defmt::write!(fmt, "TrumpetEvent::VibratoChange({:?})", v.to_bits())
+            }
+        }
+    }
+}
+
+/// An event with the time of the update that produced it.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimedEvent {
+    pub time: Millis,
+    pub event: TrumpetEvent,
+}
+
+/// Most events a single update produces, one per input.
+pub const MAX_EVENTS: usize = MAX_INPUTS;
+
+/// The events of an update. A change of an analog input replaces an earlier
+/// change of the same input, and an event that does not fit is counted
+/// rather than panicking mid performance.
+#[derive(Debug, Default)]
+struct EventQueue {
+    events: Vec<TrumpetEvent, MAX_EVENTS>,
+    dropped: u32,
+}
+
+impl EventQueue {
+    fn push(&mut self, event: TrumpetEvent) {
+        if let Some(queued) = self
+            .events
+            .iter_mut()
+            .find(|queued| event.supersedes(queued))
+        {
+            *queued = event;
+            return;
+        }
+
+        if self.events.push(event).is_err() {
+            self.dropped = self.dropped.saturating_add(1);
+        }
+    }
+}
+
+/// Temperature readings averaged until a whole interval has passed.
+#[derive(Debug, Default, Clone, Copy)]
+struct TemperatureAverage {
+    sum: I32F32,
+    count: u32,
+    /// Time of the first reading of the interval.
+    since: Option<Millis>,
+}
+
+impl TemperatureAverage {
+    /// Adds a reading, returns the average once `interval` has passed since
+    /// the first reading and starts over.
+    fn update(&mut self, reading: Celsius, now: Millis, interval: Millis) -> Option<Celsius> {
+        self.sum += I32F32::from_num(reading);
+        self.count += 1;
+        let since = *self.since.get_or_insert(now);
+
+        if now.wrapping_sub(since) < interval {
+            return None;
         }
+
+        let average = self.sum / I32F32::from_num(self.count);
+        *self = Self::default();
+        Some(Celsius::saturating_from_num(average))
     }
 }
+
+/// How the valves are read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ValveMode {
+    /// Pushbuttons, a valve is either up or down.
+    #[default]
+    Digital,
+    /// Position sensors, partially pressed valves produce half valve tones.
+    /// A valve counts as down once it is pressed past halfway.
+    Analog,
+}
+
 /// Abstraction over raw input readings, takes care of e.g. debouncing and
 /// rescaling the potentiometer values.
-pub struct TrumpetInputs<INPUTS> {
+pub struct TrumpetInputs<INPUTS, CLOCK> {
     inputs: INPUTS,
-    valve_debouncers: [Debouncer; 3],
+    clock: CLOCK,
+    valve_debouncers: [Debouncer; MAX_VALVES],
     blow_debouncer: Debouncer,
-    events: Vec<TrumpetEvent, 8>,
+    events: EventQueue,
+    /// Time of the last update, all its events happened then.
+    time: Millis,
     last_trumpet_state: TrumpetInputState,
+    /// The raw readings of the last update, held by inputs that fail to read.
+    last_reading: TrumpetInputState,
+    faults: Vec<Input, MAX_INPUTS>,
+    valve_mode: ValveMode,
+    calibration: Calibration,
+    calibrating: Option<CalibrationRoutine>,
+    calibration_error: Option<CalibrationError>,
+    /// Since when the calibration gesture is held.
+    calibration_hold: Option<Millis>,
+    analog: AnalogInputs,
+    temperature: TemperatureAverage,
 }
 
-impl<INPUTS: Inputs> TrumpetInputs<INPUTS> {
-    pub fn new(inputs: INPUTS, debounce_time: u32) -> Self {
+impl<INPUTS: Inputs, CLOCK: Clock> TrumpetInputs<INPUTS, CLOCK> {
+    /// Temperature sensors are noisy, the RP2040 one reads ~0.47°C per ADC
+    /// step, and the tuning only drifts ~3 cents per degree. So the readings
+    /// are averaged per interval and only changes of several steps are sent.
+    const TEMPERATURE_INTERVAL: Millis = 1000;
+    const TEMPERATURE_THRESHOLD: Celsius = Celsius::unwrapped_from_str("2");
+
+    /// How long all valves have to be held without blowing to start
+    /// calibrating the pots.
+    const CALIBRATION_HOLD: Millis = 3000;
+
+    /// The blowstrength pot has to be at rest for the calibration gesture, so
+    /// it is not confused with squeezing the pot for other settings.
+    const CALIBRATION_REST: BlowStrength = BlowStrength::unwrapped_from_str("0.25");
+
+    /// Buttons have to read the same for `debounce_time` milliseconds before
+    /// they change state.
+    pub fn new(inputs: INPUTS, clock: CLOCK, debounce_time: Millis) -> Self {
         Self {
             inputs,
-            valve_debouncers: [Debouncer::new(debounce_time); 3],
+            clock,
+            valve_debouncers: [Debouncer::new(debounce_time); MAX_VALVES],
             blow_debouncer: Debouncer::new(debounce_time),
-            events: Vec::new(),
+            events: EventQueue::default(),
+            time: 0,
             last_trumpet_state: TrumpetInputState::default(),
+            last_reading: TrumpetInputState::default(),
+            faults: Vec::new(),
+            valve_mode: ValveMode::default(),
+            calibration: Calibration::default(),
+            calibrating: None,
+            calibration_error: None,
+            calibration_hold: None,
+            analog: AnalogInputs::default(),
+            temperature: TemperatureAverage::default(),
         }
     }
 
+    pub fn set_valve_mode(&mut self, valve_mode: ValveMode) {
+        self.valve_mode = valve_mode;
+    }
+
+    pub fn conditioning(&self, channel: AnalogChannel) -> &ChannelSettings {
+        self.analog.channel(channel).settings()
+    }
+
+    /// Sets the smoothing and threshold of an analog input.
+    pub fn set_conditioning(&mut self, channel: AnalogChannel, settings: ChannelSettings) {
+        self.analog.channel_mut(channel).set_settings(settings);
+    }
+
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// Applies a calibration, e.g. one persisted in an earlier session.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Starts calibrating the pots, as if the gesture was held. The readings
+    /// of the next update are taken as the resting positions.
+    pub fn start_calibration(&mut self) {
+        self.calibrating = Some(CalibrationRoutine::default());
+        self.calibration_error = None;
+    }
+
+    /// Applies the travel swept since the calibration started. A failed
+    /// calibration keeps the previous one.
+    pub fn finish_calibration(&mut self) -> Result<(), CalibrationError> {
+        let Some(routine) = self.calibrating.take() else {
+            return Ok(());
+        };
+
+        let result = routine
+            .finish()
+            .map(|calibration| self.calibration = calibration);
+        self.calibration_error = result.err();
+        result
+    }
+
+    pub fn calibrating(&self) -> bool {
+        self.calibrating.is_some()
+    }
+
+    /// Why the last calibration was rejected, if it was.
+    pub fn calibration_error(&self) -> Option<CalibrationError> {
+        self.calibration_error
+    }
+
+    /// Runs the calibration gesture and routine on the raw pot readings and
+    /// the debounced buttons. Returns whether the calibration took the inputs
+    /// of this update, no events are sent while calibrating.
+    fn update_calibration(&mut self, state: &TrumpetInputState) -> bool {
+        if let Some(routine) = &mut self.calibrating {
+            if !state.blow {
+                routine.update(state.embouchure, state.blowstrength);
+                return true;
+            }
+
+            // Blowing finishes the sweep without starting a note, the note
+            // starts once the blow input is pressed again
+            self.finish_calibration().ok();
+            self.last_trumpet_state.blow = true;
+            return true;
+        }
+
+        let held = !state.blow
+            && [Valve::First, Valve::Second, Valve::Third]
+                .iter()
+                .all(|&valve| state.valve(valve))
+            && self.calibration.blowstrength.apply(state.blowstrength) < Self::CALIBRATION_REST;
+
+        if !held {
+            self.calibration_hold = None;
+            return false;
+        }
+
+        let since = *self.calibration_hold.get_or_insert(self.time);
+        if self.time.wrapping_sub(since) >= Self::CALIBRATION_HOLD {
+            self.calibration_hold = None;
+            self.start_calibration();
+        }
+
+        false
+    }
+
     fn update_debouncers(&mut self, state: TrumpetInputState) {
-        for (id, debouncer) in self.valve_debouncers.iter_mut().enumerate() {
-            debouncer.update(state.valve(Valve::from(id)));
+        for (&valve, debouncer) in Valve::ALL.iter().zip(self.valve_debouncers.iter_mut()) {
+            debouncer.update(state.valve(valve), self.time);
         }
 
-        self.blow_debouncer.update(state.blow)
+        self.blow_debouncer.update(state.blow, self.time)
     }
 
     pub fn events(&self) -> &[TrumpetEvent] {
-        &self.events
+        &self.events.events
     }
 
-    fn debouncer_is_high(debouncer: Option<&Debouncer>) -> bool {
-        let Some(debouncer) = debouncer else {
-            panic!("No debouncer found.");
-        };
+    /// The events of the last update with their time.
+    pub fn timed_events(&self) -> impl Iterator<Item = TimedEvent> + '_ {
+        self.events.events.iter().map(|&event| TimedEvent {
+            time: self.time,
+            event,
+        })
+    }
 
-        debouncer.is_high().unwrap_or(false)
+    /// Time of the last update.
+    pub fn time(&self) -> Millis {
+        self.time
+    }
+
+    /// Inputs that failed to read in the last update and hold their last
+    /// reading, e.g. a disconnected pot.
+    pub fn faults(&self) -> &[Input] {
+        &self.faults
+    }
+
+    /// Events dropped since the inputs were created, should stay zero as
+    /// `MAX_EVENTS` covers every input.
+    pub fn dropped_events(&self) -> u32 {
+        self.events.dropped
+    }
+
+    /// The debounced inputs as of the last update.
+    pub fn state(&self) -> &TrumpetInputState {
+        &self.last_trumpet_state
     }
 
     fn valve_debouncer_is_high(&self, valve: Valve) -> bool {
-        let debouncer_index = valve as usize;
-        Self::debouncer_is_high(self.valve_debouncers.get(debouncer_index))
+        self.valve_debouncers
+            .get(valve as usize)
+            .and_then(Debouncer::is_high)
+            .unwrap_or(false)
     }
 
     fn blow_debouncer_is_high(&self) -> bool {
-        Self::debouncer_is_high(Some(&self.blow_debouncer))
+        self.blow_debouncer.is_high().unwrap_or(false)
     }
 
-    pub fn update_events(&mut self) {
-        let mut current_state = TrumpetInputState::read_from(&mut self.inputs);
+    /// Reads the inputs and produces the events of this update. Should an
+    /// input fail to read the update goes ahead with its last reading, and
+    /// the error of the first faulty input is returned.
+    pub fn update_events(&mut self) -> Result<(), InputError<INPUTS::Error>> {
+        self.time = self.clock.now();
+        let (mut current_state, faults, error) =
+            TrumpetInputState::read_from(&mut self.inputs, &self.last_reading);
+        self.last_reading = current_state;
+        self.faults = faults;
+        let result = error.map_or(Ok(()), Err);
+
+        if self.valve_mode == ValveMode::Analog {
+            current_state.valves = current_state
+                .valve_positions
+                .map(|position| position > ValvePosition::MAX >> 1);
+        }
 
         self.update_debouncers(current_state);
 
-        current_state.first = self.valve_debouncer_is_high(Valve::First);
-        current_state.second = self.valve_debouncer_is_high(Valve::Second);
-        current_state.third = self.valve_debouncer_is_high(Valve::Third);
+        current_state.valves = Valve::ALL.map(|valve| self.valve_debouncer_is_high(valve));
         current_state.blow = self.blow_debouncer_is_high();
 
-        self.events.clear();
+        self.events.events.clear();
 
-        for (valve, (&current_state, &last_state)) in current_state
-            .valves()
-            .iter()
-            .zip(self.last_trumpet_state.valves().iter())
-            .enumerate()
-        {
+        if self.update_calibration(&current_state) {
+            return result;
+        }
+
+        current_state.embouchure = self.calibration.embouchure.apply(current_state.embouchure);
+        current_state.blowstrength = self
+            .calibration
+            .blowstrength
+            .apply(current_state.blowstrength);
+
+        let mut valve_toggled = [false; MAX_VALVES];
+        for (&valve, (&current_state, &last_state)) in Valve::ALL.iter().zip(
+            current_state
+                .valves
+                .iter()
+                .zip(self.last_trumpet_state.valves.iter()),
+        ) {
             let event = if !last_state && current_state {
-                TrumpetEvent::ValveDown(Valve::from(valve))
+                TrumpetEvent::ValveDown(valve)
             } else if last_state && !current_state {
-                TrumpetEvent::ValveUp(Valve::from(valve))
+                TrumpetEvent::ValveUp(valve)
             } else {
                 continue;
             };
 
-            self.events.push(event).ok().expect("Valve event dropped");
+            valve_toggled[valve as usize] = true;
+            self.events.push(event);
         }
 
         let event = if !self.last_trumpet_state.blow && current_state.blow {
@@ -132,69 +442,293 @@ impl<INPUTS: Inputs> TrumpetInputs<INPUTS> {
         };
 
         if let Some(event) = event {
-            self.events.push(event).ok().expect("Blow event dropped");
+            self.events.push(event);
         }
 
-        // TODO: make const
-        let pot_threshold: U0F16 = U0F16::from_num(20. / 65536.);
+        if let Some(blowstrength) = self
+            .analog
+            .blowstrength
+            .update(current_state.blowstrength, self.time)
+        {
+            self.events
+                .push(TrumpetEvent::BlowStrengthChange(blowstrength));
+        }
+        current_state.blowstrength = self.analog.blowstrength.value();
 
-        let enough_change = |last: U0F16, current: U0F16| {
-            ((last.saturating_sub(current)) > pot_threshold)
-                || (current.saturating_sub(last) > pot_threshold)
-        };
+        if let Some(embouchure) = self
+            .analog
+            .embouchure
+            .update(current_state.embouchure, self.time)
+        {
+            self.events.push(TrumpetEvent::EmbouchureChange(embouchure));
+        }
+        current_state.embouchure = self.analog.embouchure.value();
 
-        if enough_change(
-            self.last_trumpet_state.blowstrength,
-            current_state.blowstrength,
-        ) {
-            self.events
-                .push(TrumpetEvent::BlowStrengthChange(current_state.blowstrength))
-                .ok()
-                .expect("Blowstrength event dropped");
-        } else {
+        for ((&valve, slide), input) in Valve::ALL
+            .iter()
+            .zip(current_state.slides.iter_mut())
+            .zip(self.analog.slides.iter_mut())
+        {
+            if let Some(extension) = input.update(*slide, self.time) {
+                self.events
+                    .push(TrumpetEvent::SlideChange(valve, extension));
+            }
+            *slide = input.value();
         }
 
-        if enough_change(self.last_trumpet_state.embouchure, current_state.embouchure) {
-            self.events
-                .push(TrumpetEvent::EmbouchureChange(current_state.embouchure))
-                .ok()
-                .expect("Embouchure event dropped");
+        // The first reading is sent at once, later ones as averages
+        if let Some(reading) = current_state.temperature {
+            let changed = match self.last_trumpet_state.temperature {
+                Some(last) => self
+                    .temperature
+                    .update(reading, self.time, Self::TEMPERATURE_INTERVAL)
+                    .filter(|&average| last.abs_diff(average) >= Self::TEMPERATURE_THRESHOLD),
+                None => Some(reading),
+            };
+
+            if let Some(temperature) = changed {
+                current_state.temperature = Some(temperature);
+                self.events
+                    .push(TrumpetEvent::TemperatureChange(temperature));
+            } else {
+                current_state.temperature = self.last_trumpet_state.temperature;
+            }
+        }
+
+        // The first reading of a vibrato control is always sent, its absence
+        // means no vibrato rather than none of it
+        if let Some(vibrato) = current_state.vibrato {
+            let changed = self.analog.vibrato.update(vibrato, self.time).is_some();
+
+            if changed || self.last_trumpet_state.vibrato.is_none() {
+                self.events
+                    .push(TrumpetEvent::VibratoChange(self.analog.vibrato.value()));
+            }
+            current_state.vibrato = Some(self.analog.vibrato.value());
+        }
+
+        // Up and down events move the valve to its end position in the model,
+        // so the analog position is always sent again after a toggle.
+        if self.valve_mode == ValveMode::Analog {
+            for ((&valve, position), input) in Valve::ALL
+                .iter()
+                .zip(current_state.valve_positions.iter_mut())
+                .zip(self.analog.valve_positions.iter_mut())
+            {
+                let changed = input.update(*position, self.time).is_some();
+                *position = input.value();
+
+                if valve_toggled[valve as usize] || changed {
+                    self.events
+                        .push(TrumpetEvent::ValvePositionChange(valve, *position));
+                }
+            }
         }
 
         self.last_trumpet_state = current_state;
+        result
     }
 }
 
+/// Why a run did not go as planned, the instrument keeps playing either way.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InterfaceError<I, F> {
+    /// An input failed to read and holds its last reading.
+    Input(InputError<I>),
+    /// The FIFO refused a command, it is sent again on the next run.
+    Fifo(F),
+}
+
 // TODO: tests are possible using this library setup, e.g. fuzzers for checking panic-free-ness
 
-pub struct TrumpetInterface<FIFO, INPUTS> {
+pub struct TrumpetInterface<FIFO, INPUTS, CLOCK> {
     fifo: FIFO,
-    inputs: TrumpetInputs<INPUTS>,
+    inputs: TrumpetInputs<INPUTS, CLOCK>,
     trumpet: Trumpet,
+    /// Serialized commands the FIFO refused, sent before those of the next run.
+    pending: Vec<(CommandKind, u32), PENDING_COMMANDS>,
+    /// Pending commands dropped for newer ones while the FIFO refused them.
+    dropped_commands: u32,
 }
 
-impl<FIFO: Fifo, INPUTS: Inputs> TrumpetInterface<FIFO, INPUTS> {
-    pub fn new(io: IO<FIFO, INPUTS>, debounce_time: u32) -> Self {
+/// Commands held while the FIFO refuses them. A newer command replaces the
+/// pending one of the same kind, so this holds one of every kind the trumpet
+/// sends and nothing is dropped.
+const PENDING_COMMANDS: usize = 2 * MAX_COMMANDS;
+
+/// The part of the synth a command sets, only the latest of each matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommandKind {
+    Frequency,
+    Reconfigure(u32),
+    /// Not sent by the trumpet, never replaced.
+    Other,
+}
+
+impl CommandKind {
+    fn of(command: &Command) -> Self {
+        match command.message {
+            CommandMessage::Frequency(..) => CommandKind::Frequency,
+            CommandMessage::Reconfigure(payload) => {
+                CommandKind::Reconfigure(TrumpetSynthCommand::tag(payload))
+            }
+            _ => CommandKind::Other,
+        }
+    }
+}
+
+impl<FIFO: Fifo, INPUTS: Inputs, CLOCK: Clock> TrumpetInterface<FIFO, INPUTS, CLOCK> {
+    pub fn new(io: IO<FIFO, INPUTS, CLOCK>, debounce_time: Millis) -> Self {
+        Self::with_definition(io, debounce_time, BFLAT_TRUMPET)
+    }
+
+    pub fn with_definition(
+        io: IO<FIFO, INPUTS, CLOCK>,
+        debounce_time: Millis,
+        definition: TrumpetDefinition,
+    ) -> Self {
+        Self::with_trumpet(io, debounce_time, Trumpet::new(definition))
+    }
+
+    pub fn with_preset(
+        io: IO<FIFO, INPUTS, CLOCK>,
+        debounce_time: Millis,
+        preset: &TrumpetPreset,
+    ) -> Self {
+        Self::with_trumpet(io, debounce_time, preset.trumpet())
+    }
+
+    pub fn with_trumpet(
+        io: IO<FIFO, INPUTS, CLOCK>,
+        debounce_time: Millis,
+        trumpet: Trumpet,
+    ) -> Self {
         Self {
             fifo: io.fifo,
-            inputs: TrumpetInputs::new(io.inputs, debounce_time),
-            trumpet: Trumpet::new(BFLAT_TRUMPET),
+            inputs: TrumpetInputs::new(io.inputs, io.clock, debounce_time),
+            trumpet,
+            pending: Vec::new(),
+            dropped_commands: 0,
         }
     }
 
-    pub fn run(&mut self) {
-        self.inputs.update_events();
-        let commands = self.trumpet.update(self.inputs.events());
+    /// Switches to another instrument, keeping the current input state.
+    pub fn set_preset(&mut self, preset: &TrumpetPreset) {
+        self.trumpet.set_definition(preset.definition);
+        self.trumpet.set_tuning(preset.tuning());
+    }
+
+    pub fn set_valve_mode(&mut self, valve_mode: ValveMode) {
+        self.inputs.set_valve_mode(valve_mode);
+    }
 
-        if self.inputs.events().len() > 0 {
-            // defmt::info!("events: {:?}", self.inputs.events());
-        }
-        if commands.len() > 0 {
-            // defmt::info!("commands: {:?}", commands.len());
-        }
+    pub fn configure(&mut self, setting: TrumpetSetting) {
+        self.trumpet.configure(setting);
+    }
+
+    pub fn input_state(&self) -> &TrumpetInputState {
+        self.inputs.state()
+    }
+
+    /// Inputs that failed to read in the last run, see `TrumpetInputs::faults`.
+    pub fn input_faults(&self) -> &[Input] {
+        self.inputs.faults()
+    }
+
+    /// Events and synth commands dropped because they did not fit, see
+    /// `TrumpetInputs::dropped_events`, or because the FIFO kept refusing them.
+    pub fn dropped(&self) -> u32 {
+        self.inputs
+            .dropped_events()
+            .saturating_add(self.trumpet.dropped_commands())
+            .saturating_add(self.dropped_commands)
+    }
+
+    /// The events of the last run with their time, e.g. for recording a performance.
+    pub fn events(&self) -> impl Iterator<Item = TimedEvent> + '_ {
+        self.inputs.timed_events()
+    }
+
+    pub fn conditioning(&self, channel: AnalogChannel) -> &ChannelSettings {
+        self.inputs.conditioning(channel)
+    }
+
+    /// Sets the smoothing and threshold of an analog input, see `analog`.
+    pub fn set_conditioning(&mut self, channel: AnalogChannel, settings: ChannelSettings) {
+        self.inputs.set_conditioning(channel, settings);
+    }
+
+    pub fn calibration(&self) -> &Calibration {
+        self.inputs.calibration()
+    }
+
+    /// Applies a calibration of the pots, e.g. one persisted in an earlier session.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.inputs.set_calibration(calibration);
+    }
+
+    /// Starts calibrating the pots, see `TrumpetInputs::start_calibration`.
+    pub fn start_calibration(&mut self) {
+        self.inputs.start_calibration();
+    }
+
+    pub fn finish_calibration(&mut self) -> Result<(), CalibrationError> {
+        self.inputs.finish_calibration()
+    }
+
+    /// Whether the pots are being calibrated, the instrument is silent meanwhile.
+    pub fn calibrating(&self) -> bool {
+        self.inputs.calibrating()
+    }
+
+    pub fn calibration_error(&self) -> Option<CalibrationError> {
+        self.inputs.calibration_error()
+    }
+
+    pub fn trumpet(&self) -> &Trumpet {
+        &self.trumpet
+    }
+
+    /// Retunes the running trumpet, e.g. with a partial map fitted to the player.
+    pub fn set_tuning(&mut self, tuning: TrumpetTuning) {
+        self.trumpet.set_tuning(tuning);
+    }
+
+    /// Reads the inputs, updates the trumpet and sends the commands to the
+    /// synth. Errors are reported after the run went ahead: faulty inputs hold
+    /// their last reading and refused commands are sent again next run.
+    pub fn run(&mut self) -> Result<(), InterfaceError<INPUTS::Error, FIFO::Error>> {
+        let inputs = self.inputs.update_events();
+        let commands = self
+            .trumpet
+            .update(self.inputs.events(), self.inputs.time());
 
         for command in commands {
-            self.fifo.write(command.serialize())
+            let kind = CommandKind::of(&command);
+            if kind != CommandKind::Other {
+                self.pending.retain(|&(pending, _)| pending != kind);
+            }
+
+            if self.pending.is_full() {
+                self.pending.remove(0);
+                self.dropped_commands = self.dropped_commands.saturating_add(1);
+            }
+            self.pending.push((kind, command.serialize())).ok();
+        }
+
+        let fifo = self.flush();
+
+        inputs.map_err(InterfaceError::Input)?;
+        fifo.map_err(InterfaceError::Fifo)
+    }
+
+    /// Writes the pending commands in order until the FIFO refuses one.
+    fn flush(&mut self) -> Result<(), FIFO::Error> {
+        while let Some(&(_, command)) = self.pending.first() {
+            self.fifo.write(command)?;
+            self.pending.remove(0);
         }
+
+        Ok(())
     }
 }
@@ -1,64 +1,204 @@
-use crate::trumpet::{BlowStrength, Embouchure, Valve};
+use fixed::types::U0F16;
+use heapless::Vec;
 
-pub struct IO<FIFO, INPUTS> {
+use crate::{
+    environment::Celsius,
+    trumpet::{BlowStrength, Embouchure, SlideExtension, Valve, ValvePosition, MAX_VALVES},
+};
+
+pub struct IO<FIFO, INPUTS, CLOCK> {
     pub fifo: FIFO,
     pub inputs: INPUTS,
+    pub clock: CLOCK,
 }
 
 // TODO: output with RGB LED for feedback or mode display
 
-impl<FIFO, INPUTS> IO<FIFO, INPUTS>
+impl<FIFO, INPUTS, CLOCK> IO<FIFO, INPUTS, CLOCK>
 where
     FIFO: Fifo,
     INPUTS: Inputs,
+    CLOCK: Clock,
 {
-    pub fn new(fifo: FIFO, inputs: INPUTS) -> Self {
-        Self { fifo, inputs }
+    pub fn new(fifo: FIFO, inputs: INPUTS, clock: CLOCK) -> Self {
+        Self {
+            fifo,
+            inputs,
+            clock,
+        }
     }
 }
 
 pub trait Fifo {
-    fn write(&mut self, value: u32);
+    type Error;
+
+    /// Sends a command to the synth, an error means it was not sent and is
+    /// tried again on the next update, e.g. when the FIFO is full.
+    fn write(&mut self, value: u32) -> Result<(), Self::Error>;
 }
 
+/// Milliseconds since an arbitrary moment, e.g. power on. Wraps around after
+/// about 49 days, so compare times with `wrapping_sub`.
+pub type Millis = u32;
+
+pub trait Clock {
+    fn now(&mut self) -> Millis;
+}
+
+/// Reading an input fails e.g. on an ADC timeout. A failed read is retried,
+/// should it keep failing the input holds its last reading and is flagged as
+/// faulty until it reads again, see `TrumpetInputState::read_from`.
 pub trait Inputs {
-    fn valve(&mut self, valve: Valve) -> bool;
-    fn blow(&mut self) -> bool;
-    fn embouchure(&mut self) -> Embouchure;
-    fn blowstrength(&mut self) -> BlowStrength;
+    type Error;
+
+    fn valve(&mut self, valve: Valve) -> Result<bool, Self::Error>;
+    fn blow(&mut self) -> Result<bool, Self::Error>;
+    fn embouchure(&mut self) -> Result<Embouchure, Self::Error>;
+    fn blowstrength(&mut self) -> Result<BlowStrength, Self::Error>;
+
+    /// Extension of the slide on the given valve, e.g. the first valve thumb
+    /// trigger or third valve ring. Digital triggers report fully in or out.
+    /// On slide instruments the first valve slide moves the main slide.
+    fn slide(&mut self, _valve: Valve) -> Result<SlideExtension, Self::Error> {
+        Ok(SlideExtension::ZERO)
+    }
+
+    /// How far the valve is pressed, for valves with e.g. a hall effect
+    /// sensor. Pushbutton valves are either up or fully down.
+    fn valve_position(&mut self, valve: Valve) -> Result<ValvePosition, Self::Error> {
+        Ok(if self.valve(valve)? {
+            ValvePosition::MAX
+        } else {
+            ValvePosition::ZERO
+        })
+    }
+
+    /// Air temperature near the instrument, None without a sensor.
+    fn temperature(&mut self) -> Result<Option<Celsius>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Amount of vibrato from a dedicated control, e.g. a pressure pad or
+    /// modwheel, None without one.
+    fn vibrato(&mut self) -> Result<Option<U0F16>, Self::Error> {
+        Ok(None)
+    }
+}
+
+/// A single input of the instrument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Valve(Valve),
+    Blow,
+    Embouchure,
+    Blowstrength,
+    Slide(Valve),
+    ValvePosition(Valve),
+    Temperature,
+    Vibrato,
+}
+
+/// Amount of inputs: a button, slide and position per valve, and the rest.
+pub const MAX_INPUTS: usize = 3 * MAX_VALVES + 5;
+
+/// An input that failed to read, with the error of its last attempt.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InputError<E> {
+    pub input: Input,
+    pub error: E,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct TrumpetInputState {
-    pub first: bool,                // Pushbutton
-    pub second: bool,               // Pushbutton
-    pub third: bool,                // Pushbutton
-    pub blow: bool,                 // Pushbutton
-    pub embouchure: Embouchure,     // Linear potentiometer
-    pub blowstrength: BlowStrength, // Linear potentiometer
+    pub valves: [bool; MAX_VALVES],                   // Pushbuttons
+    pub blow: bool,                                   // Pushbutton
+    pub embouchure: Embouchure,                       // Linear potentiometer
+    pub blowstrength: BlowStrength,                   // Linear potentiometer
+    pub slides: [SlideExtension; MAX_VALVES],         // Trigger or linear potentiometer
+    pub valve_positions: [ValvePosition; MAX_VALVES], // Hall effect sensor
+    pub temperature: Option<Celsius>,                 // Temperature sensor
+    pub vibrato: Option<U0F16>,                       // Linear potentiometer
 }
 
-impl TrumpetInputState {
-    pub fn read_from<I: Inputs>(inputs: &mut I) -> Self {
-        Self {
-            first: inputs.valve(Valve::First),
-            second: inputs.valve(Valve::Second),
-            third: inputs.valve(Valve::Third),
-            blow: inputs.blow(),
-            embouchure: inputs.embouchure(),
-            blowstrength: inputs.blowstrength(),
+/// Reads inputs with the retry and hold policy of `Inputs`.
+struct InputReader<'a, I: Inputs> {
+    inputs: &'a mut I,
+    faults: Vec<Input, MAX_INPUTS>,
+    error: Option<InputError<I::Error>>,
+}
+
+impl<I: Inputs> InputReader<'_, I> {
+    /// Attempts to read an input before it counts as faulty.
+    const ATTEMPTS: usize = 3;
+
+    fn read<T>(
+        &mut self,
+        input: Input,
+        last: T,
+        mut read: impl FnMut(&mut I) -> Result<T, I::Error>,
+    ) -> T {
+        let mut result = read(self.inputs);
+        for _ in 1..Self::ATTEMPTS {
+            if result.is_ok() {
+                break;
+            }
+            result = read(self.inputs);
+        }
+
+        match result {
+            Ok(value) => value,
+            Err(error) => {
+                // Every input is read once, so the faults always fit
+                self.faults.push(input).ok();
+                self.error.get_or_insert(InputError { input, error });
+                last
+            }
         }
     }
+}
 
-    pub(crate) fn valves(&self) -> [bool; 3] {
-        [self.first, self.second, self.third]
+impl TrumpetInputState {
+    /// Reads all inputs, inputs that fail to read hold their value in `last`.
+    /// Returns the faulty inputs and the error of the first of them.
+    pub fn read_from<I: Inputs>(
+        inputs: &mut I,
+        last: &TrumpetInputState,
+    ) -> (Self, Vec<Input, MAX_INPUTS>, Option<InputError<I::Error>>) {
+        let mut reader = InputReader {
+            inputs,
+            faults: Vec::new(),
+            error: None,
+        };
+
+        let state = Self {
+            valves: Valve::ALL.map(|valve| {
+                reader.read(Input::Valve(valve), last.valve(valve), |inputs| {
+                    inputs.valve(valve)
+                })
+            }),
+            blow: reader.read(Input::Blow, last.blow, I::blow),
+            embouchure: reader.read(Input::Embouchure, last.embouchure, I::embouchure),
+            blowstrength: reader.read(Input::Blowstrength, last.blowstrength, I::blowstrength),
+            slides: Valve::ALL.map(|valve| {
+                reader.read(Input::Slide(valve), last.slides[valve as usize], |inputs| {
+                    inputs.slide(valve)
+                })
+            }),
+            valve_positions: Valve::ALL.map(|valve| {
+                reader.read(
+                    Input::ValvePosition(valve),
+                    last.valve_positions[valve as usize],
+                    |inputs| inputs.valve_position(valve),
+                )
+            }),
+            temperature: reader.read(Input::Temperature, last.temperature, I::temperature),
+            vibrato: reader.read(Input::Vibrato, last.vibrato, I::vibrato),
+        };
+
+        (state, reader.faults, reader.error)
     }
 
     pub(crate) fn valve(&self, id: Valve) -> bool {
-        match id {
-            Valve::First => self.first,
-            Valve::Second => self.second,
-            Valve::Third => self.third,
-        }
+        self.valves[id as usize]
     }
 }
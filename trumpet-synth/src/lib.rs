@@ -1,5 +1,20 @@
 #![no_std]
+pub mod analog;
+pub mod articulation;
+pub mod assist;
+pub mod calibration;
+pub mod debounce;
+pub mod dynamics;
+pub mod effects;
+pub mod environment;
+pub mod fingering;
 pub mod interface;
 pub mod io;
+pub mod lungs;
+pub mod pitch;
+pub mod presets;
+pub mod settings;
 pub mod synth;
 pub mod trumpet;
+pub mod tuning;
+pub mod vibrato;
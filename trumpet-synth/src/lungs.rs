@@ -0,0 +1,128 @@
+//! Simulated breath for practicing phrasing. Playing uses up air, faster when
+//! blowing hard or playing high, and the tone fades and wavers when the
+//! player runs out. Releasing the blow input takes a breath.
+
+use fixed::types::{I16F16, U0F16, U16F16, U32F32};
+
+use crate::{io::Millis, pitch::Cents, trumpet::BlowStrength};
+
+#[derive(Debug, Clone)]
+pub struct Lungs {
+    /// Air left, 1 is a full breath. Millisecond updates use little air, so
+    /// it is kept more precisely than reported.
+    air: U32F32,
+    capacity: Millis,
+    refill: Millis,
+    /// State of the noise that makes the pitch waver.
+    noise: u32,
+    wobble: Cents,
+}
+
+impl Lungs {
+    /// How long a full breath lasts at full pressure in the low register.
+    pub const DEFAULT_CAPACITY: Millis = 15_000;
+
+    /// How long a full breath takes to fill the lungs again.
+    pub const DEFAULT_REFILL: Millis = 500;
+
+    /// Air left when the tone starts to fade and waver.
+    const LOW_AIR: U32F32 = U32F32::unwrapped_from_str("0.2");
+
+    /// Extra air used per overtone, high notes need a faster air stream.
+    const REGISTER_COST: U32F32 = U32F32::unwrapped_from_str("0.1");
+
+    /// Pitch wavering with empty lungs.
+    const MAX_WOBBLE: Cents = Cents::unwrapped_from_str("25");
+
+    /// How long the wavering takes to follow the noise.
+    const WOBBLE_TIME: Millis = 40;
+
+    pub fn set_capacity(&mut self, capacity: Millis) {
+        self.capacity = capacity.max(1);
+    }
+
+    pub fn set_refill(&mut self, refill: Millis) {
+        self.refill = refill.max(1);
+    }
+
+    /// Air left, 1 is a full breath.
+    pub fn air(&self) -> U16F16 {
+        U16F16::saturating_from_num(self.air)
+    }
+
+    /// Called once per update with the time since the last one. Uses air
+    /// while a note sounds and breathes in while the blow input is released.
+    pub fn update(
+        &mut self,
+        blow: bool,
+        lung_pressure: BlowStrength,
+        overtone: Option<u8>,
+        elapsed: Millis,
+    ) {
+        if elapsed == 0 {
+            return;
+        }
+
+        if !blow {
+            let breathed = U32F32::from_num(elapsed) / u64::from(self.refill);
+            self.air = self.air.saturating_add(breathed).min(U32F32::ONE);
+            self.wobble = Cents::ZERO;
+            return;
+        }
+
+        let Some(overtone) = overtone else {
+            return;
+        };
+
+        let register = U32F32::ONE + Self::REGISTER_COST * u64::from(overtone);
+        let used = (U32F32::from_num(lung_pressure) * register).saturating_mul_int(elapsed.into())
+            / u64::from(self.capacity);
+        self.air = self.air.saturating_sub(used);
+
+        // Smoothed noise, so the pitch wavers rather than jitters
+        let target = Self::MAX_WOBBLE * I16F16::from_num(self.instability()) * self.next_noise();
+        let follow = I16F16::from_num(elapsed.min(Self::WOBBLE_TIME)) / Self::WOBBLE_TIME as i32;
+        self.wobble += (target - self.wobble) * follow;
+    }
+
+    /// Volume factor, fades the tone out over the last of the air.
+    pub fn strength(&self) -> U0F16 {
+        U0F16::saturating_from_num(self.air / Self::LOW_AIR)
+    }
+
+    /// 0 with enough air, up to 1 when the lungs are empty.
+    pub fn instability(&self) -> U0F16 {
+        U0F16::MAX - self.strength()
+    }
+
+    /// How far the pitch wavers from the note.
+    pub fn wobble(&self) -> Cents {
+        self.wobble
+    }
+
+    /// Whether the tone is changing from lack of air.
+    pub fn running_out(&self) -> bool {
+        self.air < Self::LOW_AIR
+    }
+
+    /// Noise between -1 and 1 from a xorshift generator.
+    fn next_noise(&mut self) -> I16F16 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+
+        I16F16::from_bits((self.noise >> 15) as i32 - (1 << 16))
+    }
+}
+
+impl Default for Lungs {
+    fn default() -> Self {
+        Self {
+            air: U32F32::ONE,
+            capacity: Self::DEFAULT_CAPACITY,
+            refill: Self::DEFAULT_REFILL,
+            noise: 0x1234_5678,
+            wobble: Cents::ZERO,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Fixed point helpers for working with musical intervals in cents.
+
+use core::fmt;
+
+use fixed::types::{I16F16, U16F16, U24F8, U32F32};
+use rytmos_engrave::staff::{Accidental, Note};
+
+/// Interval in cents, 100 cents to an equal tempered semitone.
+pub type Cents = I16F16;
+
+/// Frequency ratio, e.g. 2 for an octave.
+pub type Ratio = U16F16;
+
+const CENTS_PER_OCTAVE: i32 = 1200;
+const CENTS_PER_SEMITONE: i32 = 100;
+
+/// Equal tempered ratios of the semitones within an octave.
+const SEMITONE_RATIOS: [Ratio; 12] = [
+    Ratio::unwrapped_from_str("1.0"),
+    Ratio::unwrapped_from_str("1.0594630943592953"),
+    Ratio::unwrapped_from_str("1.122462048309373"),
+    Ratio::unwrapped_from_str("1.189207115002721"),
+    Ratio::unwrapped_from_str("1.2599210498948732"),
+    Ratio::unwrapped_from_str("1.3348398541700344"),
+    Ratio::unwrapped_from_str("1.4142135623730951"),
+    Ratio::unwrapped_from_str("1.4983070768766815"),
+    Ratio::unwrapped_from_str("1.5874010519681994"),
+    Ratio::unwrapped_from_str("1.6817928305074290"),
+    Ratio::unwrapped_from_str("1.7817974362806785"),
+    Ratio::unwrapped_from_str("1.8877486253633870"),
+];
+
+const LN_2: U32F32 = U32F32::unwrapped_from_str("0.6931471805599453");
+
+/// 2^(cents / 1200). Octaves are applied as shifts, semitones from a table and
+/// the remaining fraction of a semitone with a short Taylor series.
+pub fn cents_to_ratio(cents: Cents) -> Ratio {
+    let whole_cents = cents.floor().to_num::<i32>();
+    let octaves = whole_cents.div_euclid(CENTS_PER_OCTAVE);
+    let within_octave = whole_cents.rem_euclid(CENTS_PER_OCTAVE);
+    let semitone = within_octave / CENTS_PER_SEMITONE;
+
+    let rest = cents - Cents::from_num(octaves * CENTS_PER_OCTAVE + semitone * CENTS_PER_SEMITONE);
+    let y = U32F32::from_num(rest) / U32F32::from_num(CENTS_PER_OCTAVE) * LN_2;
+    let fraction = U32F32::ONE + y + y * y / 2 + y * y * y / 6;
+
+    let ratio = U32F32::from_num(SEMITONE_RATIOS[semitone as usize]) * fraction;
+    let ratio = if octaves >= 0 {
+        ratio.saturating_mul(U32F32::from_num(1u32 << octaves.min(15)))
+    } else {
+        ratio >> (-octaves).min(31) as u32
+    };
+
+    Ratio::saturating_from_num(ratio)
+}
+
+/// Multiplies a frequency by a ratio without losing the precision of the ratio.
+pub fn scale(frequency: U24F8, ratio: Ratio) -> U24F8 {
+    U24F8::saturating_from_num(U32F32::from_num(frequency) * U32F32::from_num(ratio))
+}
+
+/// 1200 * log2(ratio). The whole octaves follow from the highest set bit, the
+/// fraction of an octave is found bit by bit by repeated squaring.
+pub fn ratio_to_cents(ratio: Ratio) -> Cents {
+    if ratio == Ratio::ZERO {
+        return Cents::MIN;
+    }
+
+    let octaves = Ratio::INT_NBITS as i32 - 1 - ratio.leading_zeros() as i32;
+    let mut y = U32F32::from_num(ratio);
+    y = if octaves >= 0 {
+        y >> octaves as u32
+    } else {
+        y << (-octaves) as u32
+    };
+
+    let mut fraction = I16F16::ZERO;
+    let mut bit = I16F16::ONE;
+    for _ in 0..I16F16::FRAC_NBITS {
+        y = y * y;
+        bit >>= 1;
+        if y >= 2 {
+            y >>= 1;
+            fraction += bit;
+        }
+    }
+
+    (Cents::from_num(octaves) + fraction) * CENTS_PER_OCTAVE
+}
+
+/// Frequency of the A above middle C everything is named against.
+pub const CONCERT_A: U24F8 = U24F8::unwrapped_from_str("440");
+const CONCERT_A_MIDI: i32 = 69;
+
+/// The note closest to a frequency, and how far off that note it is.
+#[derive(Debug, Clone, Copy)]
+pub struct NotePitch {
+    /// Midi note number, 60 is middle C.
+    pub midi: i32,
+    pub note: Note,
+    /// Deviation from the note, between -50 and 50 cents.
+    pub cents: Cents,
+}
+
+impl NotePitch {
+    /// Finds the nearest note to a frequency. `transposition` is the amount of
+    /// semitones the note is named above the sounding pitch, e.g. 2 for the
+    /// written pitch of a Bb instrument and 0 for concert pitch.
+    pub fn nearest(frequency: U24F8, transposition: i8) -> Option<Self> {
+        Self::nearest_with_reference(frequency, CONCERT_A, transposition)
+    }
+
+    /// Like `nearest`, naming notes against another frequency for the A above
+    /// middle C, e.g. 415 for baroque pitch.
+    pub fn nearest_with_reference(
+        frequency: U24F8,
+        reference: U24F8,
+        transposition: i8,
+    ) -> Option<Self> {
+        if frequency == U24F8::ZERO || reference == U24F8::ZERO {
+            return None;
+        }
+
+        let ratio =
+            Ratio::saturating_from_num(U32F32::from_num(frequency) / U32F32::from_num(reference));
+        let cents =
+            ratio_to_cents(ratio) + Cents::from_num(transposition as i32 * CENTS_PER_SEMITONE);
+
+        let semitones = (cents / CENTS_PER_SEMITONE).round();
+        let midi = CONCERT_A_MIDI + semitones.to_num::<i32>();
+
+        Some(Self {
+            midi,
+            note: midi_to_note(midi),
+            cents: cents - semitones * CENTS_PER_SEMITONE,
+        })
+    }
+}
+
+/// Spells a midi note the way brass parts usually do: sharps for C#, F# and
+/// flats for Eb, Ab and Bb.
+pub fn midi_to_note(midi: i32) -> Note {
+    let octave = (midi.div_euclid(12) - 1).max(0) as _;
+
+    match midi.rem_euclid(12) {
+        0 => Note::C(Accidental::Natural, octave),
+        1 => Note::C(Accidental::Sharp, octave),
+        2 => Note::D(Accidental::Natural, octave),
+        3 => Note::E(Accidental::Flat, octave),
+        4 => Note::E(Accidental::Natural, octave),
+        5 => Note::F(Accidental::Natural, octave),
+        6 => Note::F(Accidental::Sharp, octave),
+        7 => Note::G(Accidental::Natural, octave),
+        8 => Note::A(Accidental::Flat, octave),
+        9 => Note::A(Accidental::Natural, octave),
+        10 => Note::B(Accidental::Flat, octave),
+        _ => Note::B(Accidental::Natural, octave),
+    }
+}
+
+/// Writes a midi note as a name with octave number, e.g. Bb4, spelled like `midi_to_note`.
+pub fn write_midi_name(f: &mut fmt::Formatter<'_>, midi: i32) -> fmt::Result {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+    ];
+
+    write!(
+        f,
+        "{}{}",
+        NAMES[midi.rem_euclid(12) as usize],
+        midi.div_euclid(12) - 1
+    )
+}
+
+impl fmt::Display for NotePitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_midi_name(f, self.midi)?;
+        write!(f, " {:+}", self.cents.round().to_num::<i32>())
+    }
+}
@@ -0,0 +1,509 @@
+//! Library of brass instruments the trumpet model can play. Tube lengths are
+//! derived from the pitch of the open horn, valve tubes keep the proportions
+//! of `BFLAT_TRUMPET` unless noted. Besides valved horns there are slide and
+//! natural instruments, played with the same inputs.
+
+use fixed::types::{U12F4, U24F8};
+
+use crate::{
+    trumpet::{
+        Compensation, Embouchure, InstrumentKind, Trumpet, TrumpetDefinition, BFLAT_TRUMPET,
+        MAX_VALVES, TRUMPET_PARTIAL_CENTS,
+    },
+    tuning::TrumpetTuning,
+};
+
+pub struct TrumpetPreset {
+    pub name: &'static str,
+    pub definition: TrumpetDefinition,
+    pub embouchure_to_overtone_map: &'static [Embouchure],
+    pub bendability_per_overtone: &'static [U24F8],
+    /// Top of the pedal register, zero for instruments that don't play it.
+    pub pedal_embouchure: Embouchure,
+}
+
+impl TrumpetPreset {
+    /// The maps of every preset are validated by the tests.
+    pub fn tuning(&self) -> TrumpetTuning {
+        let mut tuning = TrumpetTuning::from_valid_maps(
+            self.embouchure_to_overtone_map,
+            self.bendability_per_overtone,
+        );
+        tuning.set_pedal_register(
+            self.pedal_embouchure,
+            TrumpetTuning::DEFAULT_PEDAL_VOLUME,
+            TrumpetTuning::DEFAULT_FALSE_TONE_RANGE,
+        );
+        tuning
+    }
+
+    pub fn trumpet(&self) -> Trumpet {
+        Trumpet::with_tuning(self.definition, self.tuning())
+    }
+}
+
+// The loosest end of the embouchure plays the pedal and false tones below
+// the lowest overtone.
+const PEDAL_EMBOUCHURE: Embouchure = Embouchure::unwrapped_from_str("0.02");
+
+// High horns have the same partials as the Bb trumpet, but the top of the
+// range is harder to reach and less forgiving.
+const HIGH_TRUMPET_EMBOUCHURE_TO_OVERTONE_MAP: [Embouchure; 9] = [
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.050"),
+    Embouchure::unwrapped_from_str("0.19"),
+    Embouchure::unwrapped_from_str("0.28"),
+    Embouchure::unwrapped_from_str("0.38"),
+    Embouchure::unwrapped_from_str("0.5"),
+    Embouchure::unwrapped_from_str("0.65"),
+    Embouchure::unwrapped_from_str("0.999"),
+];
+
+const HIGH_TRUMPET_BENDABILITY_PER_OVERTONE: [U24F8; 9] = [
+    U24F8::unwrapped_from_str("1.5"),
+    U24F8::unwrapped_from_str("1.0"),
+    U24F8::unwrapped_from_str("1.0"),
+    U24F8::unwrapped_from_str("0.4"),
+    U24F8::unwrapped_from_str("0.4"),
+    U24F8::unwrapped_from_str("0.3"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.1"),
+    U24F8::unwrapped_from_str("0.0"),
+];
+
+// Piccolos are mostly played up to the 6th partial.
+const PICCOLO_EMBOUCHURE_TO_OVERTONE_MAP: [Embouchure; 7] = [
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.10"),
+    Embouchure::unwrapped_from_str("0.3"),
+    Embouchure::unwrapped_from_str("0.5"),
+    Embouchure::unwrapped_from_str("0.7"),
+    Embouchure::unwrapped_from_str("0.999"),
+];
+
+const PICCOLO_BENDABILITY_PER_OVERTONE: [U24F8; 7] = [
+    U24F8::unwrapped_from_str("1.0"),
+    U24F8::unwrapped_from_str("0.8"),
+    U24F8::unwrapped_from_str("0.5"),
+    U24F8::unwrapped_from_str("0.3"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.1"),
+    U24F8::unwrapped_from_str("0.0"),
+];
+
+// Conical bores bend more easily than the trumpet.
+const CORNET_BENDABILITY_PER_OVERTONE: [U24F8; 9] = [
+    U24F8::unwrapped_from_str("2.5"),
+    U24F8::unwrapped_from_str("2.0"),
+    U24F8::unwrapped_from_str("2.0"),
+    U24F8::unwrapped_from_str("0.7"),
+    U24F8::unwrapped_from_str("0.7"),
+    U24F8::unwrapped_from_str("0.5"),
+    U24F8::unwrapped_from_str("0.4"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.0"),
+];
+
+// The flugelhorn sits lower: more room for the bottom partials, high C is hard.
+const FLUGELHORN_EMBOUCHURE_TO_OVERTONE_MAP: [Embouchure; 9] = [
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.080"),
+    Embouchure::unwrapped_from_str("0.25"),
+    Embouchure::unwrapped_from_str("0.36"),
+    Embouchure::unwrapped_from_str("0.47"),
+    Embouchure::unwrapped_from_str("0.6"),
+    Embouchure::unwrapped_from_str("0.75"),
+    Embouchure::unwrapped_from_str("0.999"),
+];
+
+const FLUGELHORN_BENDABILITY_PER_OVERTONE: [U24F8; 9] = [
+    U24F8::unwrapped_from_str("3.0"),
+    U24F8::unwrapped_from_str("2.5"),
+    U24F8::unwrapped_from_str("2.0"),
+    U24F8::unwrapped_from_str("0.8"),
+    U24F8::unwrapped_from_str("0.7"),
+    U24F8::unwrapped_from_str("0.6"),
+    U24F8::unwrapped_from_str("0.4"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.0"),
+];
+
+// Bugle calls only use the 2nd up to the 6th partial.
+const BUGLE_EMBOUCHURE_TO_OVERTONE_MAP: [Embouchure; 6] = [
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.15"),
+    Embouchure::unwrapped_from_str("0.4"),
+    Embouchure::unwrapped_from_str("0.6"),
+    Embouchure::unwrapped_from_str("0.8"),
+];
+
+const BUGLE_BENDABILITY_PER_OVERTONE: [U24F8; 6] = [
+    U24F8::unwrapped_from_str("1.0"),
+    U24F8::unwrapped_from_str("1.0"),
+    U24F8::unwrapped_from_str("0.8"),
+    U24F8::unwrapped_from_str("0.5"),
+    U24F8::unwrapped_from_str("0.3"),
+    U24F8::unwrapped_from_str("0.1"),
+];
+
+// The trombone lips the same partials as the trumpet an octave lower, the
+// wide mouthpiece makes them easier to bend.
+const TROMBONE_BENDABILITY_PER_OVERTONE: [U24F8; 9] = [
+    U24F8::unwrapped_from_str("2.5"),
+    U24F8::unwrapped_from_str("2.0"),
+    U24F8::unwrapped_from_str("2.0"),
+    U24F8::unwrapped_from_str("0.8"),
+    U24F8::unwrapped_from_str("0.7"),
+    U24F8::unwrapped_from_str("0.5"),
+    U24F8::unwrapped_from_str("0.4"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.0"),
+];
+
+// Without valves the natural trumpet plays its melodies in the clarino
+// register, up to the 13th partial where the slots lie close together.
+const NATURAL_TRUMPET_EMBOUCHURE_TO_OVERTONE_MAP: [Embouchure; 14] = [
+    Embouchure::unwrapped_from_str("0.000"),
+    Embouchure::unwrapped_from_str("0.015"),
+    Embouchure::unwrapped_from_str("0.040"),
+    Embouchure::unwrapped_from_str("0.12"),
+    Embouchure::unwrapped_from_str("0.2"),
+    Embouchure::unwrapped_from_str("0.27"),
+    Embouchure::unwrapped_from_str("0.34"),
+    Embouchure::unwrapped_from_str("0.41"),
+    Embouchure::unwrapped_from_str("0.48"),
+    Embouchure::unwrapped_from_str("0.55"),
+    Embouchure::unwrapped_from_str("0.62"),
+    Embouchure::unwrapped_from_str("0.69"),
+    Embouchure::unwrapped_from_str("0.77"),
+    Embouchure::unwrapped_from_str("0.999"),
+];
+
+// The out of tune 11th and 13th partials are lipped into place, so they bend
+// further than their neighbours.
+const NATURAL_TRUMPET_BENDABILITY_PER_OVERTONE: [U24F8; 14] = [
+    U24F8::unwrapped_from_str("1.5"),
+    U24F8::unwrapped_from_str("1.0"),
+    U24F8::unwrapped_from_str("1.0"),
+    U24F8::unwrapped_from_str("0.5"),
+    U24F8::unwrapped_from_str("0.4"),
+    U24F8::unwrapped_from_str("0.3"),
+    U24F8::unwrapped_from_str("0.3"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.4"),
+    U24F8::unwrapped_from_str("0.2"),
+    U24F8::unwrapped_from_str("0.4"),
+    U24F8::unwrapped_from_str("0.0"),
+];
+
+pub static PRESETS: [TrumpetPreset; 12] = [
+    TrumpetPreset {
+        name: "bb_trumpet",
+        definition: BFLAT_TRUMPET,
+        embouchure_to_overtone_map: &TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &TrumpetTuning::DEFAULT_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: PEDAL_EMBOUCHURE,
+    },
+    TrumpetPreset {
+        name: "c_trumpet",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Valved,
+            main_tube: U12F4::unwrapped_from_str("1311"),
+            valves: 3,
+            valve_tubes: [
+                U12F4::unwrapped_from_str("169.5"),
+                U12F4::unwrapped_from_str("84.7"),
+                U12F4::unwrapped_from_str("254.2"),
+                U12F4::ZERO,
+            ],
+            compensation: &[],
+            slide_tubes: [
+                U12F4::unwrapped_from_str("35.7"),
+                U12F4::ZERO,
+                U12F4::unwrapped_from_str("107"),
+                U12F4::ZERO,
+            ],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: 0,
+        },
+        embouchure_to_overtone_map: &TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &TrumpetTuning::DEFAULT_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: PEDAL_EMBOUCHURE,
+    },
+    TrumpetPreset {
+        name: "d_trumpet",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Valved,
+            main_tube: U12F4::unwrapped_from_str("1168"),
+            valves: 3,
+            valve_tubes: [
+                U12F4::unwrapped_from_str("151"),
+                U12F4::unwrapped_from_str("75.5"),
+                U12F4::unwrapped_from_str("226.5"),
+                U12F4::ZERO,
+            ],
+            compensation: &[],
+            slide_tubes: [
+                U12F4::unwrapped_from_str("31.8"),
+                U12F4::ZERO,
+                U12F4::unwrapped_from_str("95.3"),
+                U12F4::ZERO,
+            ],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: -2,
+        },
+        embouchure_to_overtone_map: &HIGH_TRUMPET_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &HIGH_TRUMPET_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: PEDAL_EMBOUCHURE,
+    },
+    TrumpetPreset {
+        name: "eb_trumpet",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Valved,
+            main_tube: U12F4::unwrapped_from_str("1102.4"),
+            valves: 3,
+            valve_tubes: [
+                U12F4::unwrapped_from_str("142.5"),
+                U12F4::unwrapped_from_str("71.2"),
+                U12F4::unwrapped_from_str("213.7"),
+                U12F4::ZERO,
+            ],
+            compensation: &[],
+            slide_tubes: [
+                U12F4::unwrapped_from_str("30"),
+                U12F4::ZERO,
+                U12F4::unwrapped_from_str("90"),
+                U12F4::ZERO,
+            ],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: -3,
+        },
+        embouchure_to_overtone_map: &HIGH_TRUMPET_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &HIGH_TRUMPET_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: PEDAL_EMBOUCHURE,
+    },
+    TrumpetPreset {
+        name: "bb_piccolo",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Valved,
+            main_tube: U12F4::unwrapped_from_str("735.8"),
+            valves: 4,
+            valve_tubes: [
+                U12F4::unwrapped_from_str("95.1"),
+                U12F4::unwrapped_from_str("47.6"),
+                U12F4::unwrapped_from_str("142.7"),
+                U12F4::unwrapped_from_str("246.4"),
+            ],
+            compensation: &[],
+            slide_tubes: [
+                U12F4::unwrapped_from_str("20"),
+                U12F4::ZERO,
+                U12F4::unwrapped_from_str("60"),
+                U12F4::ZERO,
+            ],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: -10,
+        },
+        embouchure_to_overtone_map: &PICCOLO_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &PICCOLO_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: Embouchure::ZERO,
+    },
+    TrumpetPreset {
+        name: "a_piccolo",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Valved,
+            main_tube: U12F4::unwrapped_from_str("779.5"),
+            valves: 4,
+            valve_tubes: [
+                U12F4::unwrapped_from_str("100.8"),
+                U12F4::unwrapped_from_str("50.4"),
+                U12F4::unwrapped_from_str("151.1"),
+                U12F4::unwrapped_from_str("261"),
+            ],
+            compensation: &[],
+            slide_tubes: [
+                U12F4::unwrapped_from_str("21.2"),
+                U12F4::ZERO,
+                U12F4::unwrapped_from_str("63.6"),
+                U12F4::ZERO,
+            ],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: -9,
+        },
+        embouchure_to_overtone_map: &PICCOLO_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &PICCOLO_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: Embouchure::ZERO,
+    },
+    // In Bb like the trumpet, so the same main tube wound more compactly, with
+    // shorter slides on the first and third valve.
+    TrumpetPreset {
+        name: "cornet",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Valved,
+            main_tube: U12F4::unwrapped_from_str("1470"),
+            valves: 3,
+            valve_tubes: [
+                U12F4::unwrapped_from_str("190"),
+                U12F4::unwrapped_from_str("95"),
+                U12F4::unwrapped_from_str("285"),
+                U12F4::ZERO,
+            ],
+            compensation: &[],
+            slide_tubes: [
+                U12F4::unwrapped_from_str("30"),
+                U12F4::ZERO,
+                U12F4::unwrapped_from_str("95"),
+                U12F4::ZERO,
+            ],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: 2,
+        },
+        embouchure_to_overtone_map: &TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &CORNET_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: PEDAL_EMBOUCHURE,
+    },
+    // Also in Bb. Without a first valve slide the third valve tube is made
+    // long, so 1-3 and 1-2-3 are in tune without kicking a slide.
+    TrumpetPreset {
+        name: "flugelhorn",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Valved,
+            main_tube: U12F4::unwrapped_from_str("1470"),
+            valves: 3,
+            valve_tubes: [
+                U12F4::unwrapped_from_str("190"),
+                U12F4::unwrapped_from_str("95"),
+                U12F4::unwrapped_from_str("300"),
+                U12F4::ZERO,
+            ],
+            compensation: &[],
+            slide_tubes: [
+                U12F4::ZERO,
+                U12F4::ZERO,
+                U12F4::unwrapped_from_str("60"),
+                U12F4::ZERO,
+            ],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: 2,
+        },
+        embouchure_to_overtone_map: &FLUGELHORN_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &FLUGELHORN_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: PEDAL_EMBOUCHURE,
+    },
+    // Bugle in G, has no valves so pressing them does not change the tube.
+    TrumpetPreset {
+        name: "bugle",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Natural,
+            main_tube: U12F4::unwrapped_from_str("1750"),
+            valves: 0,
+            valve_tubes: [U12F4::ZERO, U12F4::ZERO, U12F4::ZERO, U12F4::ZERO],
+            compensation: &[],
+            slide_tubes: [U12F4::ZERO; MAX_VALVES],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: 5,
+        },
+        embouchure_to_overtone_map: &BUGLE_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &BUGLE_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: Embouchure::ZERO,
+    },
+    // The fourth valve lowers a fourth, the compensating loops correct the
+    // combinations with the fourth valve that would otherwise be sharp.
+    TrumpetPreset {
+        name: "compensating_flugelhorn",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Valved,
+            main_tube: U12F4::unwrapped_from_str("1470"),
+            valves: 4,
+            valve_tubes: [
+                U12F4::unwrapped_from_str("190"),
+                U12F4::unwrapped_from_str("95"),
+                U12F4::unwrapped_from_str("285"),
+                U12F4::unwrapped_from_str("492.2"),
+            ],
+            compensation: &[
+                Compensation {
+                    valves: 0b1001,
+                    extra_tube: U12F4::unwrapped_from_str("50.3"),
+                },
+                Compensation {
+                    valves: 0b1010,
+                    extra_tube: U12F4::unwrapped_from_str("21.7"),
+                },
+                Compensation {
+                    valves: 0b1100,
+                    extra_tube: U12F4::unwrapped_from_str("86.3"),
+                },
+            ],
+            slide_tubes: [
+                U12F4::unwrapped_from_str("40"),
+                U12F4::ZERO,
+                U12F4::unwrapped_from_str("120"),
+                U12F4::ZERO,
+            ],
+            partial_cents: &TRUMPET_PARTIAL_CENTS,
+            transposition: 2,
+        },
+        embouchure_to_overtone_map: &FLUGELHORN_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &FLUGELHORN_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: PEDAL_EMBOUCHURE,
+    },
+    // Tenor trombone in Bb, an octave below the trumpet. The first valve slide
+    // input moves the slide from first to seventh position.
+    TrumpetPreset {
+        name: "trombone",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Slide { detents: false },
+            main_tube: U12F4::unwrapped_from_str("2940"),
+            valves: 0,
+            valve_tubes: [U12F4::ZERO; MAX_VALVES],
+            compensation: &[],
+            slide_tubes: [U12F4::ZERO; MAX_VALVES],
+            partial_cents: &[],
+            transposition: 0,
+        },
+        embouchure_to_overtone_map: &TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &TROMBONE_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: PEDAL_EMBOUCHURE,
+    },
+    // Baroque trumpet in D, twice the length of the modern D trumpet. Without
+    // a bell flare tuning the partials they follow the pure harmonic series.
+    TrumpetPreset {
+        name: "natural_trumpet",
+        definition: TrumpetDefinition {
+            kind: InstrumentKind::Natural,
+            main_tube: U12F4::unwrapped_from_str("2336"),
+            valves: 0,
+            valve_tubes: [U12F4::ZERO; MAX_VALVES],
+            compensation: &[],
+            slide_tubes: [U12F4::ZERO; MAX_VALVES],
+            partial_cents: &[],
+            transposition: -2,
+        },
+        embouchure_to_overtone_map: &NATURAL_TRUMPET_EMBOUCHURE_TO_OVERTONE_MAP,
+        bendability_per_overtone: &NATURAL_TRUMPET_BENDABILITY_PER_OVERTONE,
+        pedal_embouchure: Embouchure::ZERO,
+    },
+];
+
+pub fn by_index(index: usize) -> Option<&'static TrumpetPreset> {
+    PRESETS.get(index)
+}
+
+pub fn by_name(name: &str) -> Option<&'static TrumpetPreset> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+pub fn index_of(name: &str) -> Option<usize> {
+    PRESETS
+        .iter()
+        .position(|preset| preset.name.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,171 @@
+//! Settings changed on the instrument itself, for front-ends without a screen.
+//! They are chosen with a button combination, and saved to storage when the
+//! combination is released so they are kept over power off.
+//!
+//! Hold the first and third valve and squeeze the blowstrength pot fully
+//! without blowing, then the embouchure pot selects:
+//! - with the second valve down as well: the reference pitch,
+//! - with the second valve up: the transposition in semitones.
+//!
+//! Holding all three valves for three seconds without blowing or squeezing the
+//! blowstrength pot calibrates the pots: sweep both through their full travel
+//! and blow to finish. A successful calibration is saved as well.
+
+use fixed::types::{U0F16, U24F8};
+
+use crate::{
+    calibration::Calibration,
+    interface::TrumpetInterface,
+    io::{Clock, Fifo, Inputs},
+    pitch::{self, Cents},
+    trumpet::{TrumpetSetting, Valve},
+};
+
+const REFERENCE_PITCHES: [u16; 6] = [415, 430, 440, 442, 443, 466];
+const TRANSPOSITIONS: [i8; 13] = [-6, -5, -4, -3, -2, -1, 0, 1, 2, 3, 4, 5, 6];
+
+const SQUEEZED: U0F16 = U0F16::unwrapped_from_str("0.95");
+
+/// What is kept over power off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stored {
+    pub reference_pitch: U24F8,
+    pub transpose: Cents,
+    /// None until the pots were calibrated.
+    pub calibration: Option<Calibration>,
+}
+
+impl Default for Stored {
+    fn default() -> Self {
+        Self {
+            reference_pitch: pitch::CONCERT_A,
+            transpose: Cents::ZERO,
+            calibration: None,
+        }
+    }
+}
+
+/// Memory that keeps the settings over power off, e.g. a flash sector.
+pub trait Storage {
+    /// The settings saved last, None if nothing was saved yet.
+    fn load(&mut self) -> Option<Stored>;
+    fn save(&mut self, stored: Stored);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    ReferencePitch,
+    Transpose,
+}
+
+impl Mode {
+    fn options(self) -> usize {
+        match self {
+            Mode::ReferencePitch => REFERENCE_PITCHES.len(),
+            Mode::Transpose => TRANSPOSITIONS.len(),
+        }
+    }
+
+    fn setting(self, index: usize) -> TrumpetSetting {
+        match self {
+            Mode::ReferencePitch => {
+                TrumpetSetting::ReferencePitch(U24F8::from_num(REFERENCE_PITCHES[index]))
+            }
+            Mode::Transpose => {
+                TrumpetSetting::Transpose(Cents::from_num(TRANSPOSITIONS[index] as i32 * 100))
+            }
+        }
+    }
+}
+
+pub struct SettingsGesture<STORAGE> {
+    storage: STORAGE,
+    selection: Option<(Mode, usize)>,
+    stored: Stored,
+    /// A setting changed since the last save.
+    unsaved: bool,
+}
+
+impl<STORAGE: Storage> SettingsGesture<STORAGE> {
+    /// Applies the settings saved in the storage, if any.
+    pub fn load<FIFO: Fifo, INPUTS: Inputs, CLOCK: Clock>(
+        mut storage: STORAGE,
+        interface: &mut TrumpetInterface<FIFO, INPUTS, CLOCK>,
+    ) -> Self {
+        let stored = storage.load().unwrap_or_default();
+        interface.configure(TrumpetSetting::ReferencePitch(stored.reference_pitch));
+        interface.configure(TrumpetSetting::Transpose(stored.transpose));
+        if let Some(calibration) = stored.calibration {
+            interface.set_calibration(calibration);
+        }
+
+        Self {
+            storage,
+            selection: None,
+            stored,
+            unsaved: false,
+        }
+    }
+
+    pub fn storage(&self) -> &STORAGE {
+        &self.storage
+    }
+
+    /// Applies the setting selected by the inputs, if the combination is held,
+    /// and saves what changed. Call this on every update, also while an input
+    /// is faulty or the pots are calibrated. Returns the selected option and
+    /// the amount of options for feedback.
+    pub fn update<FIFO: Fifo, INPUTS: Inputs, CLOCK: Clock>(
+        &mut self,
+        interface: &mut TrumpetInterface<FIFO, INPUTS, CLOCK>,
+    ) -> Option<(usize, usize)> {
+        // A failed calibration keeps the previous one, so any change is new
+        let calibration = *interface.calibration();
+        if !interface.calibrating() && self.stored.calibration.unwrap_or_default() != calibration {
+            self.stored.calibration = Some(calibration);
+            self.storage.save(self.stored);
+        }
+
+        // Sweeping the pots while calibrating must not select settings
+        let state = interface.input_state();
+        let held = !interface.calibrating()
+            && !state.blow
+            && state.blowstrength >= SQUEEZED
+            && state.valves[Valve::First as usize]
+            && state.valves[Valve::Third as usize];
+
+        if !held {
+            if self.unsaved {
+                self.storage.save(self.stored);
+                self.unsaved = false;
+            }
+
+            self.selection = None;
+            return None;
+        }
+
+        let mode = if state.valves[Valve::Second as usize] {
+            Mode::ReferencePitch
+        } else {
+            Mode::Transpose
+        };
+
+        let index = (state.embouchure.to_bits() as usize * mode.options()) >> U0F16::FRAC_NBITS;
+
+        if self.selection != Some((mode, index)) {
+            let setting = mode.setting(index);
+            let before = self.stored;
+            match setting {
+                TrumpetSetting::ReferencePitch(pitch) => self.stored.reference_pitch = pitch,
+                TrumpetSetting::Transpose(transpose) => self.stored.transpose = transpose,
+                _ => (),
+            }
+            self.unsaved |= self.stored != before;
+
+            interface.configure(setting);
+            self.selection = Some((mode, index));
+        }
+
+        Some((index, mode.options()))
+    }
+}
@@ -1,6 +1,6 @@
-use fixed::types::I1F15;
+use fixed::types::{I16F16, I1F15, I32F32, U0F16, U12F4, U4F4, U8F8};
 use rytmos_synth::{
-    commands::CommandMessage,
+    commands::{Command, CommandMessage},
     effect::{
         lpf::{LowPassFilter, LowPassFilterSettings},
         Effect,
@@ -10,21 +10,76 @@ use rytmos_synth::{
         Synth,
     },
 };
-use serde::{Deserialize, Serialize};
+
+use crate::{
+    articulation::Articulation,
+    vibrato::{Vibrato, VibratoKind, VibratoSettings},
+};
+
+/// Samples per second the synth is run at.
+pub const SAMPLE_RATE: u32 = 24000;
 
 pub fn create() -> TrumpetSynth {
-    TrumpetSynth::make(0x0, ())
+    TrumpetSynth::make(0x0, TrumpetSynthSettings::default())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrumpetSynthSettings {
+    /// Length of the pitch glide of a lip slur in samples, 0 jumps straight
+    /// to the new partial.
+    pub glide_samples: u16,
+    /// How far the volume drops halfway through a lip slur.
+    pub slur_dip: U0F16,
+    pub vibrato: VibratoSettings,
+}
+
+impl TrumpetSynthSettings {
+    /// 20ms at 24kHz.
+    pub const DEFAULT_GLIDE_SAMPLES: u16 = 480;
+    pub const DEFAULT_SLUR_DIP: U0F16 = U0F16::unwrapped_from_str("0.3");
+}
+
+impl Default for TrumpetSynthSettings {
+    fn default() -> Self {
+        Self {
+            glide_samples: Self::DEFAULT_GLIDE_SAMPLES,
+            slur_dip: Self::DEFAULT_SLUR_DIP,
+            vibrato: VibratoSettings::default(),
+        }
+    }
+}
+
+/// A lip slur under way from one partial to the next.
+#[derive(Debug, Clone, Copy)]
+struct Glide {
+    from: U12F4,
+    to: U12F4,
+    elapsed: u16,
 }
 
 pub struct TrumpetSynth {
     sawtooth: SawtoothSynth,
     lpf: LowPassFilter,
+    settings: TrumpetSynthSettings,
+    /// How much a half pressed valve muffles the sound, 0 is fully open.
+    choke: U0F16,
+    choke_filter: I16F16,
+    /// The frequency sounding right now, trails the commanded one during a glide.
+    frequency: U12F4,
+    /// Set by a lip slur articulation, the next frequency is glided to.
+    lip_slur: bool,
+    glide: Option<Glide>,
+    /// Volume factor of the slur dip, 1 outside of a glide.
+    gain: I16F16,
+    vibrato: Vibrato,
+    /// Whether the sawtooth runs off the frequency because of lip vibrato.
+    pitch_modulated: bool,
 }
 
 impl Synth for TrumpetSynth {
-    type Settings = ();
+    type Settings = TrumpetSynthSettings;
 
-    fn make(address: u32, (): Self::Settings) -> Self
+    fn make(address: u32, settings: Self::Settings) -> Self
     where
         Self: Sized,
     {
@@ -33,10 +88,22 @@ impl Synth for TrumpetSynth {
             lpf: LowPassFilter::new(LowPassFilterSettings {
                 alpha: I1F15::unwrapped_from_str("0.01"), // higher = less filter
             }),
+            settings,
+            choke: U0F16::ZERO,
+            choke_filter: I16F16::ZERO,
+            frequency: U12F4::ZERO,
+            lip_slur: false,
+            glide: None,
+            gain: I16F16::ONE,
+            vibrato: Vibrato::new(settings.vibrato),
+            pitch_modulated: false,
         }
     }
 
-    fn configure(&mut self, (): Self::Settings) {}
+    fn configure(&mut self, settings: Self::Settings) {
+        self.settings = settings;
+        self.vibrato.configure(settings.vibrato);
+    }
 
     fn play(&mut self, _note: rytmos_engrave::staff::Note, _velocity: fixed::types::U4F4) {
         // Do nothing, trumpet synth only supports freq()
@@ -51,15 +118,28 @@ impl Synth for TrumpetSynth {
     }
 
     fn next(&mut self) -> fixed::types::I1F15 {
+        self.update_glide();
+        let tremolo = self.update_vibrato();
+
         let sample = self.sawtooth.next();
-        self.lpf.next(sample)
+        let sample = self.lpf.next(sample);
+        let sample = self.choke(sample);
+        Self::amplify(sample, self.gain * tremolo)
     }
 
     fn run_command(&mut self, command: rytmos_synth::commands::Command) {
-        self.sawtooth.run_command(command);
-
-        if let CommandMessage::Reconfigure(command_serialized) = command.message {
-            let command = TrumpetSynthCommand::deserialize(command_serialized);
+        match command.message {
+            CommandMessage::Reconfigure(command_serialized) => {
+                // Reconfigurations the trumpet cannot read are the sawtooth's
+                match TrumpetSynthCommand::deserialize(command_serialized) {
+                    Some(trumpet_command) => self.run_trumpet_command(trumpet_command),
+                    None => self.sawtooth.run_command(command),
+                }
+            }
+            CommandMessage::Frequency(frequency, volume) => {
+                self.set_frequency(command.address, frequency, volume)
+            }
+            _ => self.sawtooth.run_command(command),
         }
     }
 
@@ -68,12 +148,257 @@ impl Synth for TrumpetSynth {
     }
 }
 
+impl TrumpetSynth {
+    /// Samples between pitch updates of a glide, the sawtooth does not need
+    /// a new frequency every sample.
+    const GLIDE_CONTROL_PERIOD: u16 = 32;
+
+    /// Pitch deviation of lip vibrato at full depth, about half a semitone.
+    const LIP_VIBRATO_RANGE: I32F32 = I32F32::unwrapped_from_str("0.03");
+
+    /// Volume deviation of breath vibrato at full depth.
+    const BREATH_VIBRATO_RANGE: I16F16 = I16F16::unwrapped_from_str("0.4");
+
+    /// The frequency sounding right now.
+    pub fn frequency(&self) -> U12F4 {
+        self.frequency
+    }
+
+    fn run_trumpet_command(&mut self, command: TrumpetSynthCommand) {
+        match command {
+            TrumpetSynthCommand::FilterAlpha(alpha) => {
+                self.lpf = LowPassFilter::new(LowPassFilterSettings { alpha })
+            }
+            TrumpetSynthCommand::Choke(choke) => self.choke = choke,
+            TrumpetSynthCommand::Articulation(articulation) => {
+                self.lip_slur = articulation == Articulation::LipSlur;
+                if !self.lip_slur {
+                    // Any other articulation lands on its note straight away
+                    self.glide = None;
+                    self.gain = I16F16::ONE;
+                }
+
+                // Slurred notes carry on the vibrato of the previous note
+                if !matches!(articulation, Articulation::Slur | Articulation::LipSlur) {
+                    self.vibrato.restart();
+                }
+
+                self.attack(Self::attack_for(articulation))
+            }
+            TrumpetSynthCommand::VibratoKind(kind) => {
+                self.configure_vibrato(|settings| settings.kind = kind)
+            }
+            TrumpetSynthCommand::VibratoRate(rate) => {
+                self.configure_vibrato(|settings| settings.rate = rate)
+            }
+            TrumpetSynthCommand::VibratoDepth(depth) => {
+                self.configure_vibrato(|settings| settings.depth = depth)
+            }
+            TrumpetSynthCommand::VibratoDelay(delay) => {
+                self.configure_vibrato(|settings| settings.delay = delay)
+            }
+        }
+    }
+
+    fn configure_vibrato(&mut self, change: impl FnOnce(&mut VibratoSettings)) {
+        change(&mut self.settings.vibrato);
+        self.vibrato.configure(self.settings.vibrato);
+    }
+
+    /// Moves the pitch for lip vibrato, returns the volume factor for breath
+    /// vibrato.
+    fn update_vibrato(&mut self) -> I16F16 {
+        let modulation = self.vibrato.next();
+
+        let (pitch, volume) = match self.vibrato.settings().kind {
+            VibratoKind::Lip => (modulation, I16F16::ZERO),
+            VibratoKind::Breath => (I16F16::ZERO, modulation),
+        };
+
+        // Once the vibrato stops the sawtooth is put back on the frequency
+        if pitch != I16F16::ZERO || self.pitch_modulated {
+            let factor = I32F32::ONE + I32F32::from_num(pitch) * Self::LIP_VIBRATO_RANGE;
+            self.sawtooth.freq(U12F4::saturating_from_num(
+                I32F32::from_num(self.frequency) * factor,
+            ));
+            self.pitch_modulated = pitch != I16F16::ZERO;
+        }
+
+        I16F16::ONE + volume * Self::BREATH_VIBRATO_RANGE
+    }
+
+    fn set_frequency(&mut self, address: u32, frequency: U12F4, volume: U4F4) {
+        let lip_slur = core::mem::take(&mut self.lip_slur);
+
+        if frequency == U12F4::ZERO {
+            self.glide = None;
+        } else if lip_slur && self.frequency != U12F4::ZERO && self.settings.glide_samples > 0 {
+            self.glide = Some(Glide {
+                from: self.frequency,
+                to: frequency,
+                elapsed: 0,
+            });
+        } else if let Some(glide) = &mut self.glide {
+            // Bending while the slur is under way moves where it lands
+            glide.to = frequency;
+        }
+
+        if self.glide.is_none() {
+            self.frequency = frequency;
+            self.gain = I16F16::ONE;
+        }
+
+        self.sawtooth.run_command(Command {
+            address,
+            message: CommandMessage::Frequency(self.frequency, volume),
+        });
+    }
+
+    /// Moves the pitch towards the next partial and shapes the volume dip,
+    /// which is deepest halfway through the glide.
+    fn update_glide(&mut self) {
+        let Some(mut glide) = self.glide else {
+            return;
+        };
+
+        glide.elapsed += 1;
+
+        if glide.elapsed >= self.settings.glide_samples {
+            self.glide = None;
+            self.frequency = glide.to;
+            self.gain = I16F16::ONE;
+            self.sawtooth.freq(self.frequency);
+            return;
+        }
+
+        self.glide = Some(glide);
+
+        if glide.elapsed % Self::GLIDE_CONTROL_PERIOD != 0 {
+            return;
+        }
+
+        let progress =
+            I32F32::from_num(glide.elapsed) / I32F32::from_num(self.settings.glide_samples);
+        let from = I32F32::from_num(glide.from);
+        let to = I32F32::from_num(glide.to);
+
+        self.frequency = U12F4::saturating_from_num(from + (to - from) * progress);
+        self.sawtooth.freq(self.frequency);
+
+        let depth = I32F32::ONE - (progress * 2 - I32F32::ONE).abs();
+        self.gain =
+            I16F16::ONE - I16F16::from_num(I32F32::from_num(self.settings.slur_dip) * depth);
+    }
+
+    fn amplify(sample: I1F15, gain: I16F16) -> I1F15 {
+        if gain == I16F16::ONE {
+            return sample;
+        }
+
+        I1F15::saturating_from_num(I16F16::from_num(sample) * gain)
+    }
+
+    /// Strength of the onset transient for every articulation, a slur only
+    /// moves the pitch.
+    fn attack_for(articulation: Articulation) -> U4F4 {
+        match articulation {
+            Articulation::Slur | Articulation::LipSlur => U4F4::ZERO,
+            Articulation::Tongue => U4F4::unwrapped_from_str("1.5"),
+            Articulation::Attack => U4F4::unwrapped_from_str("2"),
+            Articulation::Accent => U4F4::unwrapped_from_str("4"),
+        }
+    }
+
+    /// Extra one pole low pass filter that closes further the more the valve is choked.
+    fn choke(&mut self, sample: I1F15) -> I1F15 {
+        let sample = I16F16::from_num(sample);
+
+        if self.choke == U0F16::ZERO {
+            self.choke_filter = sample;
+            return I1F15::saturating_from_num(sample);
+        }
+
+        let alpha = I16F16::ONE - I16F16::from_num(self.choke) * I16F16::from_num(0.95);
+        self.choke_filter += (sample - self.choke_filter) * alpha;
+
+        I1F15::saturating_from_num(self.choke_filter)
+    }
+}
+
+/// Trumpet specific synth settings, sent as the payload of a
+/// `CommandMessage::Reconfigure`: a 4 bit tag followed by 16 bits of value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum TrumpetSynthCommand {
     FilterAlpha(I1F15),
+    Choke(U0F16),
+    Articulation(Articulation),
+    VibratoKind(VibratoKind),
+    VibratoRate(U8F8),
+    VibratoDepth(U0F16),
+    VibratoDelay(u16),
 }
 
 impl TrumpetSynthCommand {
+    const FILTER_ALPHA: u32 = 0x0;
+    const CHOKE: u32 = 0x1;
+    const ARTICULATION: u32 = 0x2;
+    const VIBRATO_KIND: u32 = 0x3;
+    const VIBRATO_RATE: u32 = 0x4;
+    const VIBRATO_DEPTH: u32 = 0x5;
+    const VIBRATO_DELAY: u32 = 0x6;
+
+    pub(crate) fn serialize(self) -> u32 {
+        let (tag, value) = match self {
+            TrumpetSynthCommand::FilterAlpha(alpha) => (Self::FILTER_ALPHA, alpha.to_bits() as u16),
+            TrumpetSynthCommand::Choke(choke) => (Self::CHOKE, choke.to_bits()),
+            TrumpetSynthCommand::Articulation(articulation) => {
+                (Self::ARTICULATION, articulation as u16)
+            }
+            TrumpetSynthCommand::VibratoKind(kind) => (Self::VIBRATO_KIND, kind as u16),
+            TrumpetSynthCommand::VibratoRate(rate) => (Self::VIBRATO_RATE, rate.to_bits()),
+            TrumpetSynthCommand::VibratoDepth(depth) => (Self::VIBRATO_DEPTH, depth.to_bits()),
+            TrumpetSynthCommand::VibratoDelay(delay) => (Self::VIBRATO_DELAY, delay),
+        };
+
+        (tag << 16) | value as u32
+    }
+
+    /// The part of the synth a serialized command sets.
+    pub(crate) fn tag(command_serialized: u32) -> u32 {
+        (command_serialized >> 16) & 0xf
+    }
+
     fn deserialize(command_serialized: u32) -> Option<Self> {
-        TODO
+        let value = command_serialized as u16;
+
+        match Self::tag(command_serialized) {
+            Self::FILTER_ALPHA => Some(TrumpetSynthCommand::FilterAlpha(I1F15::from_bits(
+                value as i16,
+            ))),
+            Self::CHOKE => Some(TrumpetSynthCommand::Choke(U0F16::from_bits(value))),
+            Self::ARTICULATION => {
+                let articulation = match value {
+                    0 => Articulation::Attack,
+                    1 => Articulation::Tongue,
+                    2 => Articulation::Accent,
+                    3 => Articulation::Slur,
+                    4 => Articulation::LipSlur,
+                    _ => return None,
+                };
+                Some(TrumpetSynthCommand::Articulation(articulation))
+            }
+            Self::VIBRATO_KIND => {
+                let kind = match value {
+                    0 => VibratoKind::Lip,
+                    1 => VibratoKind::Breath,
+                    _ => return None,
+                };
+                Some(TrumpetSynthCommand::VibratoKind(kind))
+            }
+            Self::VIBRATO_RATE => Some(TrumpetSynthCommand::VibratoRate(U8F8::from_bits(value))),
+            Self::VIBRATO_DEPTH => Some(TrumpetSynthCommand::VibratoDepth(U0F16::from_bits(value))),
+            Self::VIBRATO_DELAY => Some(TrumpetSynthCommand::VibratoDelay(value)),
+            _ => None,
+        }
     }
 }
@@ -2,12 +2,24 @@
 
 use fixed::{
     traits::LossyFrom,
-    types::{U0F16, U12F4, U24F8, U4F4},
+    types::{U0F16, U12F4, U24F8, U32F32, U4F4},
 };
 use heapless::Vec;
 use rytmos_synth::commands::{Command, CommandMessage};
 
-use crate::interface::TrumpetEvent;
+use crate::{
+    articulation::{Articulation, ArticulationDetector, PlayedNote},
+    assist::PitchAssist,
+    effects::Effects,
+    environment::{Celsius, Environment, Humidity},
+    interface::TrumpetEvent,
+    io::Millis,
+    lungs::Lungs,
+    pitch::{self, Cents, NotePitch, Ratio},
+    synth::TrumpetSynthCommand,
+    tuning::TrumpetTuning,
+    vibrato::{ShakeDetector, VibratoControl, VibratoKind, VibratoSettings},
+};
 
 #[derive(Debug, Default, Clone, Copy)]
 pub enum ValveState {
@@ -34,71 +46,274 @@ impl Into<bool> for ValveState {
     }
 }
 
+/// Maximum amount of valves an instrument can have.
+pub const MAX_VALVES: usize = 4;
+
+/// Valve combinations as bit masks, the first valve is the lowest bit.
+pub type ValveCombination = u8;
+
 #[derive(Debug, Default)]
 pub struct Valves {
-    pub first: ValveState,
-    pub second: ValveState,
-    pub third: ValveState,
+    pub states: [ValveState; MAX_VALVES],
+    /// How far every valve is pressed, only in between up and down for
+    /// valves read by an analog sensor.
+    pub positions: [ValvePosition; MAX_VALVES],
 }
 
 impl Valves {
     pub fn set(&mut self, valve: Valve, state: ValveState) {
-        match valve {
-            Valve::First => self.first = state,
-            Valve::Second => self.second = state,
-            Valve::Third => self.third = state,
-        }
+        self.states[valve as usize] = state;
+        self.positions[valve as usize] = match state {
+            ValveState::Up => ValvePosition::ZERO,
+            ValveState::Down => ValvePosition::MAX,
+        };
+    }
+
+    pub fn get(&self, valve: Valve) -> ValveState {
+        self.states[valve as usize]
+    }
+
+    /// Bit mask of all valves that are currently pressed down.
+    pub fn combination(&self) -> ValveCombination {
+        self.states
+            .iter()
+            .enumerate()
+            .filter(|(_, &state)| state.into())
+            .fold(0, |mask, (valve, _)| mask | (1 << valve))
+    }
+
+    pub fn position(&self, valve: Valve) -> ValvePosition {
+        self.positions[valve as usize]
     }
 
     pub fn update(&mut self, event: TrumpetEvent) {
         match event {
             TrumpetEvent::ValveUp(valve) => self.set(valve, ValveState::Up),
             TrumpetEvent::ValveDown(valve) => self.set(valve, ValveState::Down),
+            TrumpetEvent::ValvePositionChange(valve, position) => {
+                self.positions[valve as usize] = position
+            }
             _ => (),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Valve {
     First,
     Second,
     Third,
+    Fourth,
+}
+
+impl Valve {
+    pub const ALL: [Valve; MAX_VALVES] = [Valve::First, Valve::Second, Valve::Third, Valve::Fourth];
 }
 
 impl Into<usize> for Valve {
     fn into(self) -> usize {
-        match self {
-            Valve::First => 0,
-            Valve::Second => 1,
-            Valve::Third => 2,
-        }
+        self as usize
     }
 }
 
-impl From<usize> for Valve {
-    fn from(value: usize) -> Self {
-        match value {
-            0 => Valve::First,
-            1 => Valve::Second,
-            2 => Valve::Third,
-            // TODO: some sort of hardware panic handler that turns on a (red?) led somewhere in the rytmos library to make hardware panics easier to recognize
-            _ => unreachable!(),
-        }
+impl TryFrom<usize> for Valve {
+    type Error = ();
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        Valve::ALL.get(value).copied().ok_or(())
     }
 }
 
 pub type Embouchure = U0F16;
 pub type BlowStrength = U0F16;
+/// How far a valve slide is pulled out, from fully in (0) to fully out (~1).
+pub type SlideExtension = U0F16;
+/// How far a valve is pressed, from up (0) to fully down (~1).
+pub type ValvePosition = U0F16;
+
+/// Extra tubing the air is routed through when all valves in `valves` are
+/// pressed, as found on compensating instruments.
+#[derive(Debug, Clone, Copy)]
+pub struct Compensation {
+    pub valves: ValveCombination,
+    pub extra_tube: U12F4,
+}
+
+/// How the player changes the length of the tube.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstrumentKind {
+    /// Valves route the air through extra tubing.
+    Valved,
+    /// A trombone slide extends the main tube continuously, positioned by the
+    /// slide input of the first valve. Fully out is seventh position, six
+    /// semitones below the closed slide.
+    Slide {
+        /// Catch the slide on the seven positions, like markings to aim for.
+        detents: bool,
+    },
+    /// Only the lips choose the note, e.g. the bugle and natural trumpet.
+    /// Valves do not change the tube, but still trigger effects.
+    Natural,
+}
 
 /// All lengths in mm's
 #[derive(Debug, Clone, Copy)]
 pub struct TrumpetDefinition {
-    main_tube: U12F4,
-    first_valve_tube: U12F4,
-    second_valve_tube: U12F4,
-    third_valve_tube: U12F4,
-    speed_of_sound: U24F8,
+    pub kind: InstrumentKind,
+    pub main_tube: U12F4,
+    /// Amount of valves on the instrument, further valve tubes are ignored.
+    /// More than `MAX_VALVES` counts as `MAX_VALVES`.
+    pub valves: usize,
+    pub valve_tubes: [U12F4; MAX_VALVES],
+    pub compensation: &'static [Compensation],
+    /// Tubing added by fully extending a valve slide, only in the air path
+    /// while that valve is down.
+    pub slide_tubes: [U12F4; MAX_VALVES],
+    /// Deviation of every overtone from the pure harmonic series, indexed
+    /// like the embouchure map: the first entry is the 2nd partial, the next
+    /// the 4th, see `Fingering::partial`. Missing overtones are played pure.
+    pub partial_cents: &'static [Cents],
+    /// Semitones the written part lies above the sounding pitch, e.g. 2 for
+    /// an instrument in Bb.
+    pub transposition: i8,
+}
+
+impl TrumpetDefinition {
+    /// Positions of a trombone slide, each a semitone below the previous.
+    pub const SLIDE_POSITIONS: u8 = 7;
+
+    /// Part of the slide travel around every position that catches the slide.
+    const DETENT_WIDTH: SlideExtension = SlideExtension::unwrapped_from_str("0.03");
+
+    /// Bit mask of the valves this instrument actually has.
+    pub fn valve_mask(&self) -> ValveCombination {
+        if self.kind != InstrumentKind::Valved {
+            return 0;
+        }
+
+        let valves = self.valves.min(MAX_VALVES) as u32;
+        let mask = 1u16.checked_shl(valves).unwrap_or(0).wrapping_sub(1);
+        mask as ValveCombination
+    }
+
+    /// Lengths are summed in U24F8, a trombone with its slide out is longer
+    /// than U12F4 reaches.
+    pub fn tube_length(&self, combination: ValveCombination) -> U24F8 {
+        let combination = combination & self.valve_mask();
+        let mut length = U24F8::from(self.main_tube);
+
+        for (valve, &tube) in self.valve_tubes.iter().enumerate() {
+            if combination & (1 << valve) != 0 {
+                length += U24F8::from(tube);
+            }
+        }
+
+        for compensation in self.compensation {
+            if combination & compensation.valves == compensation.valves {
+                length += U24F8::from(compensation.extra_tube);
+            }
+        }
+
+        length
+    }
+
+    /// Tube length with partially pressed valves: every valve adds the part of
+    /// its tube matching how far it is pressed. Compensation follows the
+    /// combination the valves are closest to.
+    pub fn blended_tube_length(
+        &self,
+        combination: ValveCombination,
+        positions: &[ValvePosition; MAX_VALVES],
+    ) -> U24F8 {
+        let mut length = U24F8::from(self.main_tube);
+
+        for (valve, (&tube, &position)) in self.valve_tubes.iter().zip(positions.iter()).enumerate()
+        {
+            if self.valve_mask() & (1 << valve) == 0 {
+                continue;
+            }
+
+            length += if position == ValvePosition::MAX {
+                U24F8::from(tube)
+            } else {
+                U24F8::from(tube) * U24F8::lossy_from(position)
+            };
+        }
+
+        for compensation in self.compensation {
+            if combination & compensation.valves == compensation.valves {
+                length += U24F8::from(compensation.extra_tube);
+            }
+        }
+
+        length
+    }
+
+    /// Frequency an overtone resonates at in a tube of the given length,
+    /// including the intonation of the partial.
+    pub fn harmonic(&self, tube_length: U24F8, overtone: u8, speed_of_sound: U24F8) -> U24F8 {
+        let fundamental = speed_of_sound / tube_length;
+
+        let harmonic = fundamental * U24F8::from_num(overtone + 1);
+        let intonation = pitch::cents_to_ratio(self.partial_cents(overtone));
+
+        pitch::scale(harmonic, intonation)
+    }
+
+    pub fn partial_cents(&self, overtone: u8) -> Cents {
+        self.partial_cents
+            .get(overtone as usize)
+            .copied()
+            .unwrap_or(Cents::ZERO)
+    }
+
+    /// Extra length from the valve slides that are in use for this combination.
+    pub fn slide_length(
+        &self,
+        combination: ValveCombination,
+        slides: &[SlideExtension; MAX_VALVES],
+    ) -> U12F4 {
+        let combination = combination & self.valve_mask();
+        let mut length = U12F4::ZERO;
+
+        for (valve, (&tube, &extension)) in self.slide_tubes.iter().zip(slides.iter()).enumerate() {
+            if combination & (1 << valve) != 0 {
+                length +=
+                    U12F4::saturating_from_num(U24F8::from(tube) * U24F8::lossy_from(extension));
+            }
+        }
+
+        length
+    }
+
+    /// Tubing the trombone slide adds in the given position, 0 being first
+    /// position with the slide closed. Every position lowers a semitone.
+    pub fn slide_position_length(&self, position: u8) -> U24F8 {
+        let ratio = pitch::cents_to_ratio(Cents::from_num(100 * position as i32));
+
+        U24F8::from(self.main_tube) * (U24F8::lossy_from(ratio) - U24F8::ONE)
+    }
+
+    /// Extra length from the trombone slide pulled out to `extension`, zero
+    /// on instruments without one. The slide moves linearly, so the positions
+    /// lie further apart the further out it goes.
+    pub fn main_slide_length(&self, extension: SlideExtension) -> U24F8 {
+        let InstrumentKind::Slide { detents } = self.kind else {
+            return U24F8::ZERO;
+        };
+
+        let travel = self.slide_position_length(Self::SLIDE_POSITIONS - 1);
+        let length = travel * U24F8::lossy_from(extension);
+        if !detents {
+            return length;
+        }
+
+        let catch = travel * U24F8::lossy_from(Self::DETENT_WIDTH);
+        (0..Self::SLIDE_POSITIONS)
+            .map(|position| self.slide_position_length(position))
+            .find(|&position| position.abs_diff(length) < catch)
+            .unwrap_or(length)
+    }
 }
 
 /// Represents the state of the mechanics of the trumpet, the "air" inside it,
@@ -106,48 +321,76 @@ pub struct TrumpetDefinition {
 #[derive(Debug, Default)]
 pub struct TrumpetState {
     valves: Valves,
+    slides: [SlideExtension; MAX_VALVES],
     blow: bool,
     embouchure_tightness: Embouchure,
     lung_pressure: BlowStrength,
+    /// Overtone the lips are locked into, kept until the embouchure moves
+    /// clearly past the neighbouring overtone.
+    slot: Option<u8>,
+    /// The lips buzz the pedal below overtone 0 and are in no slot.
+    pedal: bool,
+    environment: Environment,
+    /// Simulated breath, None plays forever.
+    lungs: Option<Lungs>,
+    assist: PitchAssist,
 }
 
 impl TrumpetState {
-    // Maps index (overtone) to embouchure at which the overtone resonates best
-    // 1 => Low C
-    // 2 => second line G
-    // 3 => middle C
-    // 4 => top space E
-    // 5 => top of the staff G
-    // 6 => Bb above the staff (31 cents sharp)
-    // 7 => high C
-    const EMBOUCHURE_TO_OVERTONE_MAP: [Embouchure; 9] = [
-        Embouchure::unwrapped_from_str("0.000"),
-        Embouchure::unwrapped_from_str("0.000"),
-        Embouchure::unwrapped_from_str("0.060"),
-        Embouchure::unwrapped_from_str("0.21"),
-        Embouchure::unwrapped_from_str("0.3"),
-        Embouchure::unwrapped_from_str("0.4"),
-        Embouchure::unwrapped_from_str("0.5"),
-        Embouchure::unwrapped_from_str("0.6"),
-        Embouchure::unwrapped_from_str("0.999"),
-    ];
+    pub fn tube_length(&self, def: &TrumpetDefinition) -> U24F8 {
+        let combination = self.valves.combination();
+        def.blended_tube_length(combination, &self.valves.positions)
+            + U24F8::from(def.slide_length(combination, &self.slides))
+            + def.main_slide_length(self.slides[Valve::First as usize])
+    }
 
-    pub fn tube_length(&self, def: &TrumpetDefinition) -> U12F4 {
-        let mut length = def.main_tube;
+    /// How far the most choked valve is from fully up or down, 0 when all
+    /// valves are clean and ~1 when a valve is pressed exactly halfway.
+    pub fn choke(&self, def: &TrumpetDefinition) -> U0F16 {
+        self.valves
+            .positions
+            .iter()
+            .take(def.valve_mask().count_ones() as usize)
+            .map(|&position| {
+                let from_end = position.min(ValvePosition::MAX - position);
+                from_end.saturating_mul_int(2)
+            })
+            .max()
+            .unwrap_or(U0F16::ZERO)
+    }
 
-        if self.valves.first.into() {
-            length += def.first_valve_tube;
-        }
+    pub fn lungs(&self) -> Option<&Lungs> {
+        self.lungs.as_ref()
+    }
 
-        if self.valves.second.into() {
-            length += def.second_valve_tube;
-        }
+    pub fn lungs_mut(&mut self) -> Option<&mut Lungs> {
+        self.lungs.as_mut()
+    }
 
-        if self.valves.third.into() {
-            length += def.third_valve_tube;
+    /// Advances the simulated breath by the time since the last update.
+    /// Returns whether running out of air changed the volume or pitch.
+    pub fn breathe(&mut self, elapsed: Millis) -> bool {
+        let overtone = self.overtone();
+        let Some(lungs) = &mut self.lungs else {
+            return false;
+        };
+
+        let before = (lungs.strength(), lungs.wobble());
+        lungs.update(self.blow, self.lung_pressure, overtone, elapsed);
+        overtone.is_some() && (lungs.strength(), lungs.wobble()) != before
+    }
+
+    /// Pitch deviation of a player running out of air.
+    pub fn breath_ratio(&self) -> Ratio {
+        match &self.lungs {
+            Some(lungs) if lungs.wobble() != Cents::ZERO => pitch::cents_to_ratio(lungs.wobble()),
+            _ => Ratio::ONE,
         }
+    }
 
-        length
+    /// The valves and overtone of the note that is playing, None if silent.
+    pub fn played_note(&self) -> Option<PlayedNote> {
+        Some((self.valves.combination(), self.overtone()?))
     }
 
     /// Based on the embouchure tightness and lung pressure, which overtone is playing?
@@ -157,7 +400,16 @@ impl TrumpetState {
             return None;
         }
 
-        for (i, &embouchure) in Self::EMBOUCHURE_TO_OVERTONE_MAP.iter().enumerate().rev() {
+        if self.pedal {
+            return Some(0);
+        }
+
+        self.slot
+    }
+
+    /// The overtone the embouchure would select without any slotting.
+    fn nearest_overtone(&self, tuning: &TrumpetTuning) -> Option<u8> {
+        for (i, &embouchure) in tuning.embouchure_to_overtone_map().iter().enumerate().rev() {
             if self.embouchure_tightness > embouchure {
                 return Some(i as u8);
             }
@@ -166,39 +418,129 @@ impl TrumpetState {
         None
     }
 
-    const BENDABILITY_PER_OVERTONE: [U24F8; Self::EMBOUCHURE_TO_OVERTONE_MAP.len()] = [
-        U24F8::unwrapped_from_str("2.0"),
-        U24F8::unwrapped_from_str("1.5"),
-        U24F8::unwrapped_from_str("1.5"),
-        U24F8::unwrapped_from_str("0.5"),
-        U24F8::unwrapped_from_str("0.5"),
-        U24F8::unwrapped_from_str("0.4"),
-        U24F8::unwrapped_from_str("0.3"),
-        U24F8::unwrapped_from_str("0.1"),
-        U24F8::unwrapped_from_str("0.0"),
-    ];
+    /// Width of the band around an overtone boundary the embouchure has to
+    /// cross before the lips leave their slot. More air makes it easier to move.
+    fn slot_hysteresis(&self, tuning: &TrumpetTuning) -> Embouchure {
+        let support = tuning.slot_pressure_scale() * self.lung_pressure;
+        self.assist
+            .hysteresis(tuning.slot_hysteresis() * (Embouchure::MAX - support))
+    }
+
+    /// Moves the lips to another overtone when the embouchure left the current
+    /// slot by more than the hysteresis band. Below the pedal embouchure the
+    /// lips drop out of any slot, and come back to the nearest overtone.
+    fn update_slot(&mut self, tuning: &TrumpetTuning) {
+        let hysteresis = self.slot_hysteresis(tuning);
+
+        // Nothing resonates below overtone 0, so the pedal holds the lips half
+        // as firmly as a slot, and never more than half the pedal register
+        let top = tuning.pedal_embouchure();
+        let pedal_hysteresis = (hysteresis / 2).min(top / 2);
+        self.pedal = if self.pedal {
+            self.embouchure_tightness < top.saturating_add(pedal_hysteresis)
+        } else if self.slot.is_some() {
+            self.embouchure_tightness.saturating_add(pedal_hysteresis) < top
+        } else {
+            self.embouchure_tightness < top
+        };
+
+        if self.pedal {
+            self.slot = None;
+            return;
+        }
+
+        let nearest = self.nearest_overtone(tuning);
+        let map = tuning.embouchure_to_overtone_map();
+
+        // The tuning may have been swapped for one with fewer overtones
+        if self.slot.is_some_and(|slot| slot as usize >= map.len()) {
+            self.slot = None;
+        }
+
+        self.slot = match (self.slot, nearest) {
+            (Some(slot), Some(nearest)) if nearest > slot => {
+                let boundary = map[slot as usize + 1];
+                if self.embouchure_tightness > boundary.saturating_add(hysteresis) {
+                    Some(nearest)
+                } else {
+                    Some(slot)
+                }
+            }
+            (Some(slot), nearest) if nearest < Some(slot) => {
+                let boundary = map[slot as usize];
+                if self.embouchure_tightness.saturating_add(hysteresis) < boundary {
+                    nearest
+                } else {
+                    Some(slot)
+                }
+            }
+            _ => nearest,
+        };
+    }
+
+    /// The embouchure as the slot experiences it: while the lips are held in a
+    /// slot beyond its boundaries, the note bends as far as the slot allows.
+    fn slotted_embouchure(&self, tuning: &TrumpetTuning) -> Embouchure {
+        let Some(slot) = self.slot else {
+            return self.embouchure_tightness;
+        };
+
+        let map = tuning.embouchure_to_overtone_map();
+        let Some(&lower) = map.get(slot as usize) else {
+            return self.embouchure_tightness;
+        };
+        let upper = map
+            .get(slot as usize + 1)
+            .copied()
+            .unwrap_or(Embouchure::MAX);
+
+        if upper.saturating_sub(lower) <= Embouchure::DELTA * 2 {
+            return self.embouchure_tightness;
+        }
+
+        self.embouchure_tightness
+            .clamp(lower + Embouchure::DELTA, upper - Embouchure::DELTA)
+    }
+
+    /// In the pedal register the lips are not held by a resonance: the loosest
+    /// embouchure plays the weak pedal tone an octave below overtone 0, and
+    /// tightening lips the false tones up towards overtone 0.
+    fn false_tone_bend(&self, tuning: &TrumpetTuning) -> U24F8 {
+        let top = U24F8::lossy_from(tuning.pedal_embouchure());
+        let position = U24F8::lossy_from(self.embouchure_tightness) / top;
+
+        (U24F8::ONE + position * tuning.false_tone_range()) / 2
+    }
+
+    /// Frequency factor of the lips on the partial, with the pitch assist applied.
+    pub fn bend(&self, tuning: &TrumpetTuning) -> U24F8 {
+        self.assist.bend(self.lip_bend(tuning))
+    }
+
+    fn lip_bend(&self, tuning: &TrumpetTuning) -> U24F8 {
+        if self.pedal {
+            return self.false_tone_bend(tuning);
+        }
 
-    pub fn bend(&self) -> U24F8 {
         // The higher the lung pressure the more bend there is
         // the further from the 'ideal' embouchure for the overtone, the more bend there is
+        let embouchure = self.slotted_embouchure(tuning);
         let mut emb_bend = None;
         let mut bend_up = false;
         let mut overtone = 0;
         let mut closest_overtone = None;
-        for embouchures in Self::EMBOUCHURE_TO_OVERTONE_MAP.windows(2) {
+        for embouchures in tuning.embouchure_to_overtone_map().windows(2) {
             let emb1 = embouchures[0];
             let emb2 = embouchures[1];
 
-            if self.embouchure_tightness > emb1 && self.embouchure_tightness < emb2 {
+            if embouchure > emb1 && embouchure < emb2 {
                 let max_diff = (emb2 - emb1) >> 1;
-                if self.embouchure_tightness.abs_diff(emb1)
-                    < self.embouchure_tightness.abs_diff(emb2)
-                {
-                    emb_bend = Some(max_diff - self.embouchure_tightness.abs_diff(emb1));
+                if embouchure.abs_diff(emb1) < embouchure.abs_diff(emb2) {
+                    emb_bend = Some(max_diff - embouchure.abs_diff(emb1));
                     closest_overtone = Some(overtone);
                     bend_up = false;
                 } else {
-                    emb_bend = Some(max_diff - self.embouchure_tightness.abs_diff(emb2));
+                    emb_bend = Some(max_diff - embouchure.abs_diff(emb2));
                     closest_overtone = Some(overtone + 1);
                     bend_up = true;
                 }
@@ -209,9 +551,10 @@ impl TrumpetState {
         if let (Some(emb_bend), Some(closest_overtone)) = (emb_bend, closest_overtone) {
             let bend = emb_bend;
 
-            let bend_capacity = BlowStrength::unwrapped_from_str(".9") * self.lung_pressure;
+            let bend_capacity =
+                tuning.bend_capacity() * tuning.bend_curve().apply(self.lung_pressure);
 
-            let bendability = Self::BENDABILITY_PER_OVERTONE[closest_overtone];
+            let bendability = tuning.bendability_per_overtone()[closest_overtone];
 
             if bend_up {
                 U24F8::ONE
@@ -225,48 +568,317 @@ impl TrumpetState {
         }
     }
 
-    // TODO: move all the consts to a configuration
+    pub fn volume(&self, def: &TrumpetDefinition, tuning: &TrumpetTuning) -> U4F4 {
+        let loudness = tuning.volume_curve().apply(self.lung_pressure);
+        let volume = U4F4::lossy_from(loudness) + tuning.volume_offset();
+        let damping = tuning.half_valve_damping() * self.choke(def);
+        let volume = U24F8::from(volume) * (U24F8::ONE - U24F8::lossy_from(damping));
 
-    pub fn volume(&self) -> U4F4 {
-        U4F4::lossy_from(self.lung_pressure) + U4F4::unwrapped_from_str("0.2")
+        let volume = if self.pedal {
+            volume * U24F8::lossy_from(tuning.pedal_volume())
+        } else {
+            volume
+        };
+
+        let volume = match &self.lungs {
+            Some(lungs) => volume * U24F8::lossy_from(lungs.strength()),
+            None => volume,
+        };
+
+        U4F4::saturating_from_num(volume)
     }
 
-    pub fn update(&mut self, event: TrumpetEvent) {
+    pub fn update(&mut self, event: TrumpetEvent, tuning: &TrumpetTuning) {
         self.valves.update(event);
 
         match event {
             TrumpetEvent::EmbouchureChange(fixed_i16) => self.embouchure_tightness = fixed_i16,
             TrumpetEvent::BlowStrengthChange(fixed_i16) => self.lung_pressure = fixed_i16,
-            TrumpetEvent::BlowUp => self.blow = false,
+            TrumpetEvent::BlowUp => {
+                self.blow = false;
+                self.slot = None;
+                self.pedal = false;
+            }
             TrumpetEvent::BlowDown => self.blow = true,
+            TrumpetEvent::SlideChange(valve, extension) => self.slides[valve as usize] = extension,
+            TrumpetEvent::TemperatureChange(temperature) => {
+                self.environment.temperature = temperature
+            }
             _ => (),
         }
+
+        if self.blow {
+            self.update_slot(tuning);
+        }
     }
 }
 
+// Approximate resonances of a bell-flared trumpet relative to the harmonic
+// series, per overtone. Overtone n is the partial 2(n + 1) as
+// `Fingering::partial` counts them, so the entries are the 2nd, 4th, 6th up to
+// the 18th partial: a sharp 6th, a flat 10th and 14th and an upper register
+// that drifts sharp. The 14th partial (written Bb6) is the 7th harmonic of the
+// 2nd and already lies 31 cents below equal temperament on the series alone.
+pub const TRUMPET_PARTIAL_CENTS: [Cents; 9] = [
+    Cents::unwrapped_from_str("0"),
+    Cents::unwrapped_from_str("0"),
+    Cents::unwrapped_from_str("8"),
+    Cents::unwrapped_from_str("2"),
+    Cents::unwrapped_from_str("-12"),
+    Cents::unwrapped_from_str("4"),
+    Cents::unwrapped_from_str("-10"),
+    Cents::unwrapped_from_str("5"),
+    Cents::unwrapped_from_str("10"),
+];
+
 // https://www.yamaha.com/en/musical_instrument_guide/trumpet/mechanism/mechanism002.html
 pub const BFLAT_TRUMPET: TrumpetDefinition = TrumpetDefinition {
+    kind: InstrumentKind::Valved,
     main_tube: U12F4::unwrapped_from_str("1470"),
-    first_valve_tube: U12F4::unwrapped_from_str("190"),
-    second_valve_tube: U12F4::unwrapped_from_str("95"),
-    third_valve_tube: U12F4::unwrapped_from_str("285"),
-    speed_of_sound: U24F8::unwrapped_from_str("343000"),
+    valves: 3,
+    valve_tubes: [
+        U12F4::unwrapped_from_str("190"),
+        U12F4::unwrapped_from_str("95"),
+        U12F4::unwrapped_from_str("285"),
+        U12F4::ZERO,
+    ],
+    compensation: &[],
+    slide_tubes: [
+        U12F4::unwrapped_from_str("40"),
+        U12F4::ZERO,
+        U12F4::unwrapped_from_str("120"),
+        U12F4::ZERO,
+    ],
+    partial_cents: &TRUMPET_PARTIAL_CENTS,
+    transposition: 2,
 };
 
+/// Settings of the trumpet that are changed by the player or front-end
+/// rather than read from the inputs.
+#[derive(Debug, Clone, Copy)]
+pub enum TrumpetSetting {
+    Temperature(Celsius),
+    Humidity(Humidity),
+    /// Frequency of the A above middle C in Hz, e.g. 442 or 415.
+    ReferencePitch(U24F8),
+    /// Shifts the whole instrument by an amount of cents.
+    Transpose(Cents),
+    Vibrato(VibratoSettings),
+    VibratoControl(VibratoControl),
+    /// Limits how long a phrase can be held on one breath, for practice.
+    RealisticLungs(bool),
+    /// Helps players without a trumpet embouchure play in tune.
+    PitchAssist(PitchAssist),
+}
+
+/// Most commands a single update sends to the synth.
+/// Every part of the vibrato can change in one update.
+const MAX_VIBRATO_COMMANDS: usize = 4;
+
+/// Most commands a single update sends: the articulation, the frequency and
+/// the choke, and the vibrato.
+pub const MAX_COMMANDS: usize = 3 + MAX_VIBRATO_COMMANDS;
+
 #[derive(Debug)]
 pub struct Trumpet {
     def: TrumpetDefinition,
+    tuning: TrumpetTuning,
     pub state: TrumpetState,
+    /// Last choke sent to the synth.
+    choke: U0F16,
+    reference_pitch: U24F8,
+    transpose: Cents,
+    /// Ratio from the reference pitch and transposition applied to every frequency.
+    retune: Ratio,
+    /// A setting changed the pitch, the next update resends the frequency.
+    retuned: bool,
+    articulation: ArticulationDetector,
+    last_articulation: Option<Articulation>,
+    vibrato: VibratoSettings,
+    vibrato_control: VibratoControl,
+    /// Last reading of the dedicated vibrato input.
+    vibrato_input: U0F16,
+    shake: ShakeDetector,
+    shake_depth: U0F16,
+    /// Last vibrato sent to the synth.
+    sent_vibrato: VibratoSettings,
+    effects: Effects,
+    /// Time of the last update, None before the first.
+    time: Option<Millis>,
+    /// Commands that did not fit in an update, should stay zero.
+    dropped_commands: u32,
 }
 
 impl Trumpet {
     pub fn new(def: TrumpetDefinition) -> Self {
+        Self::with_tuning(def, TrumpetTuning::default())
+    }
+
+    pub fn with_tuning(def: TrumpetDefinition, tuning: TrumpetTuning) -> Self {
         Self {
             def,
+            tuning,
             state: TrumpetState::default(),
+            choke: U0F16::ZERO,
+            reference_pitch: pitch::CONCERT_A,
+            transpose: Cents::ZERO,
+            retune: Ratio::ONE,
+            retuned: false,
+            articulation: ArticulationDetector::default(),
+            last_articulation: None,
+            vibrato: VibratoSettings::default(),
+            vibrato_control: VibratoControl::default(),
+            vibrato_input: U0F16::ZERO,
+            shake: ShakeDetector::default(),
+            shake_depth: U0F16::ZERO,
+            sent_vibrato: VibratoSettings::default(),
+            effects: Effects::default(),
+            time: None,
+            dropped_commands: 0,
+        }
+    }
+
+    pub fn definition(&self) -> &TrumpetDefinition {
+        &self.def
+    }
+
+    /// Swaps the instrument while playing, takes effect on the next update.
+    pub fn set_definition(&mut self, def: TrumpetDefinition) {
+        self.def = def;
+        self.retuned = true;
+    }
+
+    pub fn tuning(&self) -> &TrumpetTuning {
+        &self.tuning
+    }
+
+    /// Swaps the tuning while playing, takes effect on the next update.
+    pub fn set_tuning(&mut self, tuning: TrumpetTuning) {
+        self.tuning = tuning;
+        self.retuned = true;
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.state.environment
+    }
+
+    /// Applies a setting, takes effect on the next update.
+    pub fn configure(&mut self, setting: TrumpetSetting) {
+        self.retuned |= matches!(
+            setting,
+            TrumpetSetting::Temperature(_)
+                | TrumpetSetting::Humidity(_)
+                | TrumpetSetting::ReferencePitch(_)
+                | TrumpetSetting::Transpose(_)
+                | TrumpetSetting::PitchAssist(_)
+        );
+
+        match setting {
+            TrumpetSetting::Temperature(temperature) => {
+                self.state.environment.temperature = temperature
+            }
+            TrumpetSetting::Humidity(humidity) => self.state.environment.humidity = humidity,
+            TrumpetSetting::ReferencePitch(reference_pitch) => {
+                self.reference_pitch = reference_pitch;
+                self.update_retune();
+            }
+            TrumpetSetting::Transpose(transpose) => {
+                self.transpose = transpose;
+                self.update_retune();
+            }
+            TrumpetSetting::Vibrato(vibrato) => self.vibrato = vibrato,
+            TrumpetSetting::VibratoControl(control) => self.vibrato_control = control,
+            TrumpetSetting::PitchAssist(assist) => self.state.assist = assist,
+            TrumpetSetting::RealisticLungs(enabled) => {
+                if enabled != self.state.lungs.is_some() {
+                    self.state.lungs = enabled.then(Lungs::default);
+                }
+            }
         }
     }
 
+    fn update_retune(&mut self) {
+        let reference = U32F32::from_num(self.reference_pitch) / U32F32::from_num(pitch::CONCERT_A);
+        let transpose = U32F32::from_num(pitch::cents_to_ratio(self.transpose));
+
+        self.retune = Ratio::saturating_from_num(reference * transpose);
+    }
+
+    pub fn effects(&self) -> &Effects {
+        &self.effects
+    }
+
+    /// Binds gestures to effects, sets their parameters or triggers them.
+    pub fn effects_mut(&mut self) -> &mut Effects {
+        &mut self.effects
+    }
+
+    pub fn articulation_mut(&mut self) -> &mut ArticulationDetector {
+        &mut self.articulation
+    }
+
+    /// How the current note was started, None if no note is playing.
+    pub fn last_articulation(&self) -> Option<Articulation> {
+        self.last_articulation
+    }
+
+    /// The vibrato the synth plays, with the live depth of the input or
+    /// shake applied.
+    pub fn vibrato(&self) -> VibratoSettings {
+        // A flutter tongue takes over the volume wobble
+        if let Some(flutter) = self.effects.flutter() {
+            return VibratoSettings {
+                kind: VibratoKind::Breath,
+                rate: flutter.speed,
+                depth: flutter.depth,
+                delay: 0,
+            };
+        }
+
+        let amount = match self.vibrato_control {
+            VibratoControl::Fixed => return self.vibrato,
+            VibratoControl::Input => self.vibrato_input,
+            VibratoControl::Shake => self.shake_depth,
+        };
+
+        // Live vibrato is started by the player, there is nothing to delay
+        VibratoSettings {
+            depth: self.vibrato.depth * amount,
+            delay: 0,
+            ..self.vibrato
+        }
+    }
+
+    /// Synth commands for every part of the vibrato that changed since the
+    /// last update.
+    fn vibrato_commands(&mut self) -> Vec<TrumpetSynthCommand, MAX_VIBRATO_COMMANDS> {
+        let vibrato = self.vibrato();
+        let sent = core::mem::replace(&mut self.sent_vibrato, vibrato);
+
+        let changes = [
+            (vibrato.kind != sent.kind).then_some(TrumpetSynthCommand::VibratoKind(vibrato.kind)),
+            (vibrato.rate != sent.rate).then_some(TrumpetSynthCommand::VibratoRate(vibrato.rate)),
+            (vibrato.depth != sent.depth)
+                .then_some(TrumpetSynthCommand::VibratoDepth(vibrato.depth)),
+            (vibrato.delay != sent.delay)
+                .then_some(TrumpetSynthCommand::VibratoDelay(vibrato.delay)),
+        ];
+
+        changes.into_iter().flatten().collect()
+    }
+
+    pub fn pitch_assist(&self) -> PitchAssist {
+        self.state.assist
+    }
+
+    pub fn reference_pitch(&self) -> U24F8 {
+        self.reference_pitch
+    }
+
+    pub fn transpose(&self) -> Cents {
+        self.transpose
+    }
+
     /// U12F4 goes from 0 to ~4095.94 in steps of 0.0625, high notes on a trumpet
     /// rarely exceed 2kHz so this accomodates frequencies nicely.
     pub fn frequency(&self) -> Option<U24F8> {
@@ -275,36 +887,136 @@ impl Trumpet {
         };
 
         let tube_length = self.state.tube_length(&self.def);
-        let fundamental = self.def.speed_of_sound / (U24F8::from(tube_length));
+        let speed_of_sound = self.state.environment.speed_of_sound();
+        let harmonic = self.def.harmonic(tube_length, overtone, speed_of_sound);
 
-        Some(fundamental * U24F8::from_num(overtone + 1) * self.state.bend())
+        let note = pitch::scale(harmonic * self.state.bend(&self.tuning), self.retune);
+        let note = self.state.assist.quantize(note, self.reference_pitch);
+
+        Some(pitch::scale(
+            note,
+            self.state.breath_ratio() * self.effects.pitch_ratio(overtone),
+        ))
+    }
+
+    /// The sounding note nearest to the current frequency, for tuners.
+    pub fn concert_pitch(&self) -> Option<NotePitch> {
+        NotePitch::nearest_with_reference(self.frequency()?, self.reference_pitch, 0)
+    }
+
+    /// The note as it is written in the part for this instrument.
+    pub fn written_pitch(&self) -> Option<NotePitch> {
+        NotePitch::nearest_with_reference(
+            self.frequency()?,
+            self.reference_pitch,
+            self.def.transposition,
+        )
+    }
+
+    /// Time of the last update, None before the first.
+    pub fn time(&self) -> Option<Millis> {
+        self.time
     }
 
-    pub fn update(&mut self, events: &[TrumpetEvent]) -> Vec<Command, 4> {
+    /// Applies the events read at `now` and returns the commands for the
+    /// synth. Timed behaviour follows `now`, however often this is called.
+    pub fn update(&mut self, events: &[TrumpetEvent], now: Millis) -> Vec<Command, MAX_COMMANDS> {
+        let elapsed = self.time.map_or(0, |time| now.wrapping_sub(time));
+        self.time = Some(now);
+        let note_before = self.state.played_note();
+
+        let mut blowstrength = None;
         for &event in events {
-            self.state.update(event);
+            match event {
+                TrumpetEvent::VibratoChange(amount) => self.vibrato_input = amount,
+                TrumpetEvent::BlowStrengthChange(strength) => blowstrength = Some(strength),
+                _ => (),
+            }
+
+            self.state.update(event, &self.tuning);
         }
+        self.shake_depth = self.shake.update(blowstrength, now);
+        let breath_changed = self.state.breathe(elapsed);
+
+        let note_after = self.state.played_note();
+        let articulation = self.articulation.update(
+            events,
+            note_before,
+            note_after,
+            self.state.lung_pressure,
+            now,
+        );
+        if note_after.is_none() {
+            self.last_articulation = None;
+        }
+        let effects_changed = self.effects.update(events, note_after, now);
 
         let frequency = self.frequency();
-        let volume = self.state.volume();
+        let volume = self.state.volume(&self.def, &self.tuning);
+        let volume = U4F4::saturating_from_num(
+            U24F8::from(volume) * U24F8::lossy_from(self.effects.volume()),
+        );
+        let choke = self.state.choke(&self.def);
 
         let mut commands = Vec::new();
+
+        // The attack is set before the new frequency starts the note
+        if let Some(articulation) = articulation {
+            self.last_articulation = Some(articulation);
+            self.push_command(
+                &mut commands,
+                CommandMessage::Reconfigure(
+                    TrumpetSynthCommand::Articulation(articulation).serialize(),
+                ),
+            );
+        }
+
         // assume a change in state happened and the synth needs to be reconfigured
-        if events.len() > 0 {
+        if events.len() > 0 || breath_changed || effects_changed || self.retuned {
+            self.retuned = false;
+
             let frequency = if let Some(f) = frequency {
                 U12F4::wrapping_from_num(f)
             } else {
                 U12F4::ZERO
             };
 
-            commands
-                .push(Command {
-                    address: 0x0,
-                    message: CommandMessage::Frequency(frequency, volume),
-                })
-                .expect("single push in length 4 vec is safe");
+            self.push_command(&mut commands, CommandMessage::Frequency(frequency, volume));
+
+            if choke != self.choke {
+                self.choke = choke;
+                self.push_command(
+                    &mut commands,
+                    CommandMessage::Reconfigure(TrumpetSynthCommand::Choke(choke).serialize()),
+                );
+            }
+        }
+
+        for command in self.vibrato_commands() {
+            self.push_command(
+                &mut commands,
+                CommandMessage::Reconfigure(command.serialize()),
+            );
         }
 
         commands
     }
+
+    /// `MAX_COMMANDS` covers every command of an update, should one not fit
+    /// it is counted rather than panicking mid performance.
+    fn push_command(&mut self, commands: &mut Vec<Command, MAX_COMMANDS>, message: CommandMessage) {
+        let command = Command {
+            address: 0x0,
+            message,
+        };
+
+        if commands.push(command).is_err() {
+            self.dropped_commands = self.dropped_commands.saturating_add(1);
+        }
+    }
+
+    /// Commands dropped since the trumpet was created, see `push_command`.
+    pub fn dropped_commands(&self) -> u32 {
+        self.dropped_commands
+    }
 }
@@ -0,0 +1,319 @@
+//! Player-specific tuning of the trumpet model: how the embouchure maps to
+//! overtones and how strongly pitch and volume respond to the lungs.
+
+use fixed::types::{U0F16, U24F8, U4F4};
+use heapless::Vec;
+
+use crate::{
+    dynamics::ResponseCurve,
+    trumpet::{BlowStrength, Embouchure},
+};
+
+/// Upper bound on the amount of overtones a tuning can describe.
+pub const MAX_OVERTONES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TuningError {
+    /// The embouchure map contains no overtones.
+    Empty,
+    /// More than `MAX_OVERTONES` overtones were given.
+    TooManyOvertones,
+    /// The embouchure map and bendability table differ in length.
+    LengthMismatch,
+    /// The embouchure thresholds are not in ascending order.
+    NotMonotonic,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrumpetTuning {
+    embouchure_to_overtone_map: Vec<Embouchure, MAX_OVERTONES>,
+    bendability_per_overtone: Vec<U24F8, MAX_OVERTONES>,
+    bend_capacity: BlowStrength,
+    volume_offset: U4F4,
+    slot_hysteresis: Embouchure,
+    slot_pressure_scale: U0F16,
+    half_valve_damping: U0F16,
+    pedal_embouchure: Embouchure,
+    pedal_volume: U0F16,
+    false_tone_range: U24F8,
+    volume_curve: ResponseCurve,
+    bend_curve: ResponseCurve,
+}
+
+impl TrumpetTuning {
+    // Maps index (overtone) to embouchure at which the overtone resonates best
+    // 1 => Low C
+    // 2 => second line G
+    // 3 => middle C
+    // 4 => top space E
+    // 5 => top of the staff G
+    // 6 => Bb above the staff (31 cents sharp)
+    // 7 => high C
+    pub const DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP: [Embouchure; 9] = [
+        Embouchure::unwrapped_from_str("0.000"),
+        Embouchure::unwrapped_from_str("0.000"),
+        Embouchure::unwrapped_from_str("0.060"),
+        Embouchure::unwrapped_from_str("0.21"),
+        Embouchure::unwrapped_from_str("0.3"),
+        Embouchure::unwrapped_from_str("0.4"),
+        Embouchure::unwrapped_from_str("0.5"),
+        Embouchure::unwrapped_from_str("0.6"),
+        Embouchure::unwrapped_from_str("0.999"),
+    ];
+
+    pub const DEFAULT_BENDABILITY_PER_OVERTONE: [U24F8;
+        Self::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP.len()] = [
+        U24F8::unwrapped_from_str("2.0"),
+        U24F8::unwrapped_from_str("1.5"),
+        U24F8::unwrapped_from_str("1.5"),
+        U24F8::unwrapped_from_str("0.5"),
+        U24F8::unwrapped_from_str("0.5"),
+        U24F8::unwrapped_from_str("0.4"),
+        U24F8::unwrapped_from_str("0.3"),
+        U24F8::unwrapped_from_str("0.1"),
+        U24F8::unwrapped_from_str("0.0"),
+    ];
+
+    /// Fraction of the lung pressure that can be used to bend a note.
+    pub const DEFAULT_BEND_CAPACITY: BlowStrength = BlowStrength::unwrapped_from_str(".9");
+
+    /// Volume added on top of the lung pressure, so soft playing stays audible.
+    pub const DEFAULT_VOLUME_OFFSET: U4F4 = U4F4::unwrapped_from_str("0.2");
+
+    /// How far past an overtone boundary the embouchure has to move before
+    /// the lips jump to the next slot, roughly ten times the pot noise.
+    pub const DEFAULT_SLOT_HYSTERESIS: Embouchure = Embouchure::unwrapped_from_str("0.015");
+
+    /// How much of the hysteresis band full lung pressure takes away.
+    pub const DEFAULT_SLOT_PRESSURE_SCALE: U0F16 = U0F16::unwrapped_from_str("0.5");
+
+    /// Part of the volume lost with a valve pressed exactly halfway.
+    pub const DEFAULT_HALF_VALVE_DAMPING: U0F16 = U0F16::unwrapped_from_str("0.6");
+
+    /// Embouchure below which the lips leave the lowest slot for the pedal,
+    /// an octave below overtone 0. Zero leaves the pedal register out, the
+    /// presets of instruments that play pedals set their own.
+    pub const DEFAULT_PEDAL_EMBOUCHURE: Embouchure = Embouchure::ZERO;
+
+    /// Volume of the pedal register relative to the other overtones.
+    pub const DEFAULT_PEDAL_VOLUME: U0F16 = U0F16::unwrapped_from_str("0.5");
+
+    /// How far above the pedal tone the false tones reach at the top of the
+    /// pedal register, as a fraction of the pedal tone. 1 would reach
+    /// overtone 0.
+    pub const DEFAULT_FALSE_TONE_RANGE: U24F8 = U24F8::unwrapped_from_str("0.8");
+
+    /// Validates and builds a tuning. The embouchure map must be ascending and
+    /// have one bendability entry per overtone.
+    pub fn new(
+        embouchure_to_overtone_map: &[Embouchure],
+        bendability_per_overtone: &[U24F8],
+        bend_capacity: BlowStrength,
+        volume_offset: U4F4,
+    ) -> Result<Self, TuningError> {
+        Self::validate(embouchure_to_overtone_map, bendability_per_overtone)?;
+
+        let mut tuning =
+            Self::from_valid_maps(embouchure_to_overtone_map, bendability_per_overtone);
+        tuning.bend_capacity = bend_capacity;
+        tuning.volume_offset = volume_offset;
+
+        Ok(tuning)
+    }
+
+    /// Builds a tuning from tables that are known to be valid, like the
+    /// defaults and the presets, without checking them. Overtones beyond
+    /// `MAX_OVERTONES` are left out.
+    pub(crate) fn from_valid_maps(
+        embouchure_to_overtone_map: &[Embouchure],
+        bendability_per_overtone: &[U24F8],
+    ) -> Self {
+        Self {
+            embouchure_to_overtone_map: embouchure_to_overtone_map
+                .iter()
+                .take(MAX_OVERTONES)
+                .copied()
+                .collect(),
+            bendability_per_overtone: bendability_per_overtone
+                .iter()
+                .take(MAX_OVERTONES)
+                .copied()
+                .collect(),
+            bend_capacity: Self::DEFAULT_BEND_CAPACITY,
+            volume_offset: Self::DEFAULT_VOLUME_OFFSET,
+            slot_hysteresis: Self::DEFAULT_SLOT_HYSTERESIS,
+            slot_pressure_scale: Self::DEFAULT_SLOT_PRESSURE_SCALE,
+            half_valve_damping: Self::DEFAULT_HALF_VALVE_DAMPING,
+            pedal_embouchure: Self::DEFAULT_PEDAL_EMBOUCHURE,
+            pedal_volume: Self::DEFAULT_PEDAL_VOLUME,
+            false_tone_range: Self::DEFAULT_FALSE_TONE_RANGE,
+            volume_curve: ResponseCurve::default(),
+            bend_curve: ResponseCurve::default(),
+        }
+    }
+
+    /// Builds a tuning from an embouchure map and bendability table, keeping
+    /// the default bend capacity and volume offset.
+    pub fn from_maps(
+        embouchure_to_overtone_map: &[Embouchure],
+        bendability_per_overtone: &[U24F8],
+    ) -> Result<Self, TuningError> {
+        Self::new(
+            embouchure_to_overtone_map,
+            bendability_per_overtone,
+            Self::DEFAULT_BEND_CAPACITY,
+            Self::DEFAULT_VOLUME_OFFSET,
+        )
+    }
+
+    pub fn validate(
+        embouchure_to_overtone_map: &[Embouchure],
+        bendability_per_overtone: &[U24F8],
+    ) -> Result<(), TuningError> {
+        if embouchure_to_overtone_map.is_empty() {
+            return Err(TuningError::Empty);
+        }
+
+        if embouchure_to_overtone_map.len() > MAX_OVERTONES {
+            return Err(TuningError::TooManyOvertones);
+        }
+
+        if embouchure_to_overtone_map.len() != bendability_per_overtone.len() {
+            return Err(TuningError::LengthMismatch);
+        }
+
+        if embouchure_to_overtone_map
+            .windows(2)
+            .any(|embouchures| embouchures[0] > embouchures[1])
+        {
+            return Err(TuningError::NotMonotonic);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the embouchure map and bendability table, leaving the tuning
+    /// untouched if the new tables are invalid.
+    pub fn set_maps(
+        &mut self,
+        embouchure_to_overtone_map: &[Embouchure],
+        bendability_per_overtone: &[U24F8],
+    ) -> Result<(), TuningError> {
+        let tuning = Self::new(
+            embouchure_to_overtone_map,
+            bendability_per_overtone,
+            self.bend_capacity,
+            self.volume_offset,
+        )?;
+
+        self.embouchure_to_overtone_map = tuning.embouchure_to_overtone_map;
+        self.bendability_per_overtone = tuning.bendability_per_overtone;
+
+        Ok(())
+    }
+
+    pub fn set_bend_capacity(&mut self, bend_capacity: BlowStrength) {
+        self.bend_capacity = bend_capacity;
+    }
+
+    pub fn set_volume_offset(&mut self, volume_offset: U4F4) {
+        self.volume_offset = volume_offset;
+    }
+
+    /// Sets how firmly the lips stay in an overtone. `pressure_scale` is the
+    /// part of the band that disappears at full lung pressure.
+    pub fn set_slot_hysteresis(&mut self, hysteresis: Embouchure, pressure_scale: U0F16) {
+        self.slot_hysteresis = hysteresis;
+        self.slot_pressure_scale = pressure_scale;
+    }
+
+    pub fn set_half_valve_damping(&mut self, half_valve_damping: U0F16) {
+        self.half_valve_damping = half_valve_damping;
+    }
+
+    /// Sets where the pedal register below overtone 0 starts and how it
+    /// responds, see `TrumpetState::bend`.
+    pub fn set_pedal_register(
+        &mut self,
+        pedal_embouchure: Embouchure,
+        pedal_volume: U0F16,
+        false_tone_range: U24F8,
+    ) {
+        self.pedal_embouchure = pedal_embouchure;
+        self.pedal_volume = pedal_volume;
+        self.false_tone_range = false_tone_range;
+    }
+
+    /// Sets how the lung pressure translates to loudness.
+    pub fn set_volume_curve(&mut self, volume_curve: ResponseCurve) {
+        self.volume_curve = volume_curve;
+    }
+
+    /// Sets how the lung pressure translates to the part of the bend capacity
+    /// that is available.
+    pub fn set_bend_curve(&mut self, bend_curve: ResponseCurve) {
+        self.bend_curve = bend_curve;
+    }
+
+    pub fn overtones(&self) -> usize {
+        self.embouchure_to_overtone_map.len()
+    }
+
+    pub fn embouchure_to_overtone_map(&self) -> &[Embouchure] {
+        &self.embouchure_to_overtone_map
+    }
+
+    pub fn bendability_per_overtone(&self) -> &[U24F8] {
+        &self.bendability_per_overtone
+    }
+
+    pub fn bend_capacity(&self) -> BlowStrength {
+        self.bend_capacity
+    }
+
+    pub fn volume_offset(&self) -> U4F4 {
+        self.volume_offset
+    }
+
+    pub fn slot_hysteresis(&self) -> Embouchure {
+        self.slot_hysteresis
+    }
+
+    pub fn slot_pressure_scale(&self) -> U0F16 {
+        self.slot_pressure_scale
+    }
+
+    pub fn half_valve_damping(&self) -> U0F16 {
+        self.half_valve_damping
+    }
+
+    pub fn pedal_embouchure(&self) -> Embouchure {
+        self.pedal_embouchure
+    }
+
+    pub fn pedal_volume(&self) -> U0F16 {
+        self.pedal_volume
+    }
+
+    pub fn false_tone_range(&self) -> U24F8 {
+        self.false_tone_range
+    }
+
+    pub fn volume_curve(&self) -> &ResponseCurve {
+        &self.volume_curve
+    }
+
+    pub fn bend_curve(&self) -> &ResponseCurve {
+        &self.bend_curve
+    }
+}
+
+impl Default for TrumpetTuning {
+    fn default() -> Self {
+        Self::from_valid_maps(
+            &Self::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP,
+            &Self::DEFAULT_BENDABILITY_PER_OVERTONE,
+        )
+    }
+}
@@ -0,0 +1,190 @@
+//! Vibrato, either a pitch wobble made with the lips or a volume wobble made
+//! with the breath. The synth generates the wave itself so it stays smooth,
+//! the player only sets how much of it there is.
+
+use fixed::types::{I16F16, I32F32, U0F16, U0F32, U8F8};
+
+use crate::{io::Millis, synth::SAMPLE_RATE, trumpet::BlowStrength};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VibratoKind {
+    /// Pitch modulation, as made by moving the jaw or lips.
+    #[default]
+    Lip,
+    /// Amplitude modulation, as made by pulsing the air stream.
+    Breath,
+}
+
+/// Where the vibrato depth comes from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VibratoControl {
+    /// Every note gets the configured depth after the delay.
+    #[default]
+    Fixed,
+    /// A dedicated input, e.g. a pressure pad or modwheel, scales the depth.
+    Input,
+    /// Shaking the blowstrength pot scales the depth, the harder the shake
+    /// the deeper the vibrato.
+    Shake,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VibratoSettings {
+    pub kind: VibratoKind,
+    /// Wobbles per second.
+    pub rate: U8F8,
+    /// 0 is no vibrato, 1 is the widest the synth goes.
+    pub depth: U0F16,
+    /// Time in ms a note is held straight before the vibrato fades in.
+    pub delay: u16,
+}
+
+impl VibratoSettings {
+    pub const DEFAULT_RATE: U8F8 = U8F8::unwrapped_from_str("5.5");
+    pub const DEFAULT_DELAY: u16 = 300;
+}
+
+impl Default for VibratoSettings {
+    fn default() -> Self {
+        Self {
+            kind: VibratoKind::default(),
+            rate: Self::DEFAULT_RATE,
+            depth: U0F16::ZERO,
+            delay: Self::DEFAULT_DELAY,
+        }
+    }
+}
+
+/// Low frequency oscillator with a delayed onset, runs at the sample rate.
+#[derive(Debug, Clone)]
+pub struct Vibrato {
+    settings: VibratoSettings,
+    phase: U0F32,
+    increment: U0F32,
+    /// Samples since the note started, saturates once the vibrato is in.
+    elapsed: u32,
+    delay_samples: u32,
+    /// Length of the fade in, one period of the vibrato.
+    fade_samples: u32,
+}
+
+impl Vibrato {
+    pub fn new(settings: VibratoSettings) -> Self {
+        let mut vibrato = Self {
+            settings,
+            phase: U0F32::ZERO,
+            increment: U0F32::ZERO,
+            elapsed: 0,
+            delay_samples: 0,
+            fade_samples: 0,
+        };
+        vibrato.configure(settings);
+
+        vibrato
+    }
+
+    pub fn settings(&self) -> VibratoSettings {
+        self.settings
+    }
+
+    pub fn configure(&mut self, settings: VibratoSettings) {
+        self.settings = settings;
+
+        let rate = settings.rate.to_bits() as u64;
+        self.increment = U0F32::from_bits(((rate << 24) / SAMPLE_RATE as u64) as u32);
+        self.delay_samples = settings.delay as u32 * (SAMPLE_RATE / 1000);
+        self.fade_samples = ((SAMPLE_RATE as u64) << 8).checked_div(rate).unwrap_or(0) as u32;
+    }
+
+    /// Holds the next note straight again for the delay.
+    pub fn restart(&mut self) {
+        self.elapsed = 0;
+        self.phase = U0F32::ZERO;
+    }
+
+    /// The modulation for the next sample, between -depth and depth.
+    pub fn next(&mut self) -> I16F16 {
+        if self.settings.depth == U0F16::ZERO {
+            return I16F16::ZERO;
+        }
+
+        let fade_end = self.delay_samples + self.fade_samples;
+        if self.elapsed < fade_end {
+            self.elapsed += 1;
+        }
+
+        if self.elapsed <= self.delay_samples {
+            return I16F16::ZERO;
+        }
+
+        self.phase = self.phase.wrapping_add(self.increment);
+
+        // Parabolic approximation of a sine, starting upwards at phase 0
+        let t = I32F32::from_num(self.phase) * 2 - I32F32::ONE;
+        let wave = -t * 4 * (I32F32::ONE - t.abs());
+
+        let mut depth = I32F32::from_num(self.settings.depth);
+        if self.elapsed < fade_end {
+            depth = depth * I32F32::from_num(self.elapsed - self.delay_samples)
+                / I32F32::from_num(self.fade_samples);
+        }
+
+        I16F16::saturating_from_num(wave * depth)
+    }
+}
+
+/// Turns a shaking blowstrength pot into a vibrato depth. The swing between
+/// two changes of direction sets the depth, which falls away when the shaking
+/// stops.
+#[derive(Debug, Clone, Default)]
+pub struct ShakeDetector {
+    last: Option<BlowStrength>,
+    rising: bool,
+    /// Blowstrength at the last change of direction.
+    turn: Option<BlowStrength>,
+    /// Time of the last change of direction.
+    turned: Millis,
+    depth: U0F16,
+}
+
+impl ShakeDetector {
+    /// Longest half period still counted as shaking.
+    const MAX_HALF_PERIOD: Millis = 250;
+
+    /// Swing of the pot that gives full depth.
+    const FULL_SWING: I32F32 = I32F32::unwrapped_from_str("0.2");
+
+    /// Called once per update with the new blowstrength, if it changed, and
+    /// the time of the update.
+    pub fn update(&mut self, blowstrength: Option<BlowStrength>, now: Millis) -> U0F16 {
+        let since_turn = now.wrapping_sub(self.turned);
+
+        if let Some(current) = blowstrength {
+            if let Some(last) = self.last {
+                let rising = current > last;
+                if rising != self.rising {
+                    if let Some(turn) = self.turn {
+                        if since_turn <= Self::MAX_HALF_PERIOD {
+                            let swing = I32F32::from_num(turn.abs_diff(last));
+                            self.depth = U0F16::saturating_from_num(swing / Self::FULL_SWING);
+                        }
+                    }
+
+                    self.rising = rising;
+                    self.turn = Some(last);
+                    self.turned = now;
+                }
+            }
+
+            self.last = Some(current);
+        }
+
+        if now.wrapping_sub(self.turned) > Self::MAX_HALF_PERIOD {
+            self.depth = U0F16::ZERO;
+        }
+
+        self.depth
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::interface;
+use fixed::types::U0F16;
+use trumpet_synth::{
+    calibration::{Calibration, CalibrationError, PotCalibration},
+    trumpet::{BlowStrength, Embouchure},
+};
+
+#[test]
+fn test_pot_calibration() {
+    let pot = PotCalibration::new(
+        U0F16::from_num(0.1),
+        U0F16::from_num(0.1),
+        U0F16::from_num(0.85),
+        PotCalibration::DEFAULT_DEAD_ZONE,
+    )
+    .unwrap();
+
+    // The travel that is there covers the full range
+    assert_eq!(pot.apply(U0F16::from_num(0.05)), U0F16::ZERO);
+    assert_eq!(pot.apply(U0F16::from_num(0.11)), U0F16::ZERO);
+    assert_eq!(pot.apply(U0F16::from_num(0.84)), U0F16::MAX);
+    assert!((pot.apply(U0F16::from_num(0.475)).to_num::<f64>() - 0.5).abs() < 0.01);
+
+    assert_eq!(
+        PotCalibration::new(
+            U0F16::from_num(0.4),
+            U0F16::from_num(0.4),
+            U0F16::from_num(0.5),
+            PotCalibration::DEFAULT_DEAD_ZONE,
+        ),
+        Err(CalibrationError::RangeTooSmall)
+    );
+    assert_eq!(
+        PotCalibration::new(
+            U0F16::from_num(0.1),
+            U0F16::from_num(0.9),
+            U0F16::from_num(0.8),
+            PotCalibration::DEFAULT_DEAD_ZONE,
+        ),
+        Err(CalibrationError::Invalid)
+    );
+}
+
+#[test]
+fn test_calibration_persists() {
+    let calibration = Calibration {
+        embouchure: PotCalibration::new(
+            U0F16::from_num(0.05),
+            U0F16::from_num(0.5),
+            U0F16::from_num(0.9),
+            PotCalibration::DEFAULT_DEAD_ZONE,
+        )
+        .unwrap(),
+        blowstrength: PotCalibration::IDENTITY,
+    };
+
+    let bytes = calibration.to_bytes();
+    assert_eq!(Calibration::from_bytes(&bytes), Ok(calibration));
+    assert_eq!(
+        Calibration::from_bytes(&[0xff; Calibration::SIZE]),
+        Err(CalibrationError::Invalid)
+    );
+}
+
+#[test]
+fn test_calibration_gesture() {
+    let (mut interface, readings) = interface();
+    readings.borrow_mut().embouchure = 0.3;
+    readings.borrow_mut().blowstrength = 0.1;
+    readings.borrow_mut().valves = [true; 3];
+
+    // Holding all valves without blowing starts the calibration
+    for _ in 0..1000 {
+        interface.run().unwrap();
+        if interface.calibrating() {
+            break;
+        }
+    }
+    assert!(interface.calibrating());
+
+    for (embouchure, blowstrength) in [(0.3, 0.1), (0.15, 0.3), (0.8, 0.85), (0.3, 0.1)] {
+        readings.borrow_mut().embouchure = embouchure;
+        readings.borrow_mut().blowstrength = blowstrength;
+        interface.run().unwrap();
+    }
+
+    // Blowing ends the sweep without playing a note
+    readings.borrow_mut().blow = true;
+    interface.run().unwrap();
+    assert!(!interface.calibrating());
+    assert_eq!(interface.calibration_error(), None);
+    interface.run().unwrap();
+    assert_eq!(interface.trumpet().frequency(), None);
+
+    let calibration = interface.calibration();
+    assert_eq!(calibration.embouchure.center, Embouchure::from_num(0.3));
+    assert_eq!(calibration.blowstrength.min, BlowStrength::from_num(0.1));
+
+    readings.borrow_mut().embouchure = 0.8;
+    readings.borrow_mut().blowstrength = 0.85;
+    interface.run().unwrap();
+    assert_eq!(interface.input_state().embouchure, Embouchure::MAX);
+    assert_eq!(interface.input_state().blowstrength, BlowStrength::MAX);
+}
+
+#[test]
+fn test_failed_calibration_keeps_previous() {
+    let (mut interface, readings) = interface();
+    readings.borrow_mut().embouchure = 0.5;
+
+    interface.start_calibration();
+    interface.run().unwrap();
+    assert_eq!(
+        interface.finish_calibration(),
+        Err(CalibrationError::RangeTooSmall)
+    );
+    assert_eq!(interface.calibration(), &Calibration::default());
+
+    interface.run().unwrap();
+    assert_eq!(
+        interface.input_state().embouchure,
+        Embouchure::from_num(0.5)
+    );
+}
@@ -0,0 +1,160 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use heapless::Vec;
+use rytmos_synth::commands::Command;
+use trumpet_synth::{
+    environment::Celsius,
+    interface::{TrumpetEvent, TrumpetInterface},
+    io::{Clock, Fifo, Inputs, Millis, IO},
+    trumpet::{BlowStrength, Embouchure, SlideExtension, Trumpet, Valve, MAX_COMMANDS},
+};
+
+/// Time between updates in the tests, as a front-end reading every 10ms.
+pub const UPDATE_INTERVAL: Millis = 10;
+
+pub trait Tick {
+    /// Updates the trumpet `UPDATE_INTERVAL` after its last update.
+    fn tick(&mut self, events: &[TrumpetEvent]) -> Vec<Command, MAX_COMMANDS>;
+}
+
+impl Tick for Trumpet {
+    fn tick(&mut self, events: &[TrumpetEvent]) -> Vec<Command, MAX_COMMANDS> {
+        let now = self.time().unwrap_or(0) + UPDATE_INTERVAL;
+        self.update(events, now)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Disconnected;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Full;
+
+/// What the test inputs read and what the test FIFO was sent, set and
+/// checked by the test while the interface runs.
+#[derive(Default)]
+pub struct Readings {
+    pub valves: [bool; 3],
+    pub blow: bool,
+    pub embouchure: f64,
+    pub blowstrength: f64,
+    pub temperature: Option<f64>,
+    /// Failed reads of the embouchure pot left before it reads again.
+    pub embouchure_failures: u32,
+    /// Whether the slide pot of the first valve fails to read.
+    pub slide_disconnected: bool,
+    /// Whether the FIFO refuses commands.
+    pub refused: bool,
+    pub written: std::vec::Vec<u32>,
+}
+
+pub type SharedReadings = Rc<RefCell<Readings>>;
+
+pub struct TestInputs(pub SharedReadings);
+
+impl Inputs for TestInputs {
+    type Error = Disconnected;
+
+    fn valve(&mut self, valve: Valve) -> Result<bool, Disconnected> {
+        Ok(self
+            .0
+            .borrow()
+            .valves
+            .get(valve as usize)
+            .copied()
+            .unwrap_or(false))
+    }
+
+    fn blow(&mut self) -> Result<bool, Disconnected> {
+        Ok(self.0.borrow().blow)
+    }
+
+    fn embouchure(&mut self) -> Result<Embouchure, Disconnected> {
+        let mut readings = self.0.borrow_mut();
+        if readings.embouchure_failures > 0 {
+            readings.embouchure_failures -= 1;
+            return Err(Disconnected);
+        }
+
+        Ok(Embouchure::saturating_from_num(readings.embouchure))
+    }
+
+    fn blowstrength(&mut self) -> Result<BlowStrength, Disconnected> {
+        Ok(BlowStrength::saturating_from_num(
+            self.0.borrow().blowstrength,
+        ))
+    }
+
+    fn slide(&mut self, valve: Valve) -> Result<SlideExtension, Disconnected> {
+        match valve {
+            Valve::First if self.0.borrow().slide_disconnected => Err(Disconnected),
+            _ => Ok(SlideExtension::ZERO),
+        }
+    }
+
+    fn temperature(&mut self) -> Result<Option<Celsius>, Disconnected> {
+        Ok(self
+            .0
+            .borrow()
+            .temperature
+            .map(Celsius::saturating_from_num))
+    }
+}
+
+pub struct TestFifo(pub SharedReadings);
+
+impl Fifo for TestFifo {
+    type Error = Full;
+
+    fn write(&mut self, value: u32) -> Result<(), Full> {
+        let mut readings = self.0.borrow_mut();
+        if readings.refused {
+            return Err(Full);
+        }
+
+        readings.written.push(value);
+        Ok(())
+    }
+}
+
+/// Advances `UPDATE_INTERVAL` every update.
+#[derive(Default)]
+pub struct TestClock(pub Millis);
+
+impl Clock for TestClock {
+    fn now(&mut self) -> Millis {
+        self.0 += UPDATE_INTERVAL;
+        self.0
+    }
+}
+
+/// Reads the time set by the test.
+pub struct SharedClock(pub Rc<Cell<Millis>>);
+
+impl Clock for SharedClock {
+    fn now(&mut self) -> Millis {
+        self.0.get()
+    }
+}
+
+/// An interface on the test inputs and FIFO, updated every 10ms without
+/// debouncing.
+pub fn interface() -> (
+    TrumpetInterface<TestFifo, TestInputs, TestClock>,
+    SharedReadings,
+) {
+    let readings = SharedReadings::default();
+    let io = IO::new(
+        TestFifo(Rc::clone(&readings)),
+        TestInputs(Rc::clone(&readings)),
+        TestClock::default(),
+    );
+
+    (TrumpetInterface::new(io, 0), readings)
+}
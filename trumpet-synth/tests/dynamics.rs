@@ -0,0 +1,78 @@
+mod common;
+
+use common::Tick;
+use fixed::types::{U0F16, U8F8};
+use trumpet_synth::{
+    dynamics::{CurveError, ResponseCurve},
+    interface::TrumpetEvent,
+    trumpet::{BlowStrength, Embouchure, Trumpet, BFLAT_TRUMPET},
+    tuning::TrumpetTuning,
+};
+
+fn response(curve: &ResponseCurve, blowstrength: f64) -> f64 {
+    curve
+        .apply(BlowStrength::from_num(blowstrength))
+        .to_num::<f64>()
+}
+
+#[test]
+fn test_response_curves() {
+    for blowstrength in [0., 0.25, 0.5, 0.75] {
+        assert_eq!(response(&ResponseCurve::Linear, blowstrength), blowstrength);
+    }
+
+    let exponential = ResponseCurve::Exponential(U8F8::from_num(4));
+    assert_eq!(response(&exponential, 0.), 0.);
+    assert!((response(&exponential, 0.5) - 3. / 15.).abs() < 0.001);
+    assert!(response(&exponential, 0.999) > 0.99);
+
+    // Every quarter of the range is 10dB on a 40dB curve
+    let decibels = ResponseCurve::Decibels(U8F8::from_num(40));
+    assert_eq!(response(&decibels, 0.), 0.);
+    assert!((response(&decibels, 0.75) - 0.3162).abs() < 0.001);
+    assert!((response(&decibels, 0.5) - 0.1).abs() < 0.001);
+    assert!((response(&decibels, 0.25) - 0.0316).abs() < 0.001);
+
+    let points = [(0.2, 0.), (0.4, 0.6), (0.8, 0.9)]
+        .map(|(x, y)| (BlowStrength::from_num(x), U0F16::from_num(y)));
+    let piecewise = ResponseCurve::piecewise(&points).unwrap();
+    assert_eq!(response(&piecewise, 0.1), 0.);
+    assert!((response(&piecewise, 0.3) - 0.3).abs() < 0.001);
+    assert!((response(&piecewise, 0.6) - 0.75).abs() < 0.001);
+    assert!((response(&piecewise, 0.95) - 0.9).abs() < 0.001);
+
+    assert_eq!(ResponseCurve::piecewise(&[]), Err(CurveError::Empty));
+    assert_eq!(
+        ResponseCurve::piecewise(&[points[1], points[0]]),
+        Err(CurveError::NotMonotonic)
+    );
+    assert_eq!(
+        ResponseCurve::piecewise(&[points[0]; 9]),
+        Err(CurveError::TooManyPoints)
+    );
+}
+
+#[test]
+fn test_volume_curve() {
+    let volumes = |tuning: TrumpetTuning| {
+        let mut trumpet = Trumpet::with_tuning(BFLAT_TRUMPET, tuning);
+        [0.5, 0.99].map(|blowstrength| {
+            trumpet.tick(&[
+                TrumpetEvent::BlowDown,
+                TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(blowstrength)),
+                TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.25)),
+            ]);
+            trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning())
+        })
+    };
+
+    let [linear_mf, linear_ff] = volumes(TrumpetTuning::default());
+
+    let mut tuning = TrumpetTuning::default();
+    tuning.set_volume_curve(ResponseCurve::Decibels(U8F8::from_num(30)));
+    let [decibels_mf, decibels_ff] = volumes(tuning);
+
+    // Same fortissimo, but half the breath sounds much softer
+    assert_eq!(decibels_ff, linear_ff);
+    assert!(decibels_mf < linear_mf);
+}
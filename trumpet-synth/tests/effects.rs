@@ -0,0 +1,192 @@
+mod common;
+
+use common::Tick;
+use fixed::types::{U12F4, U4F4};
+use rytmos_synth::commands::{Command, CommandMessage};
+use trumpet_synth::{
+    effects::{Effect, EffectBinding, EffectError, Gesture, MAX_BINDINGS},
+    interface::TrumpetEvent,
+    trumpet::{BlowStrength, Embouchure, Trumpet, Valve, BFLAT_TRUMPET},
+    vibrato::VibratoKind,
+};
+
+fn playing_trumpet() -> Trumpet {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.25)),
+    ]);
+    trumpet
+}
+
+/// The frequency and volume sent in an update, if any.
+fn sent_note(commands: &[Command]) -> Option<(U12F4, U4F4)> {
+    commands.iter().find_map(|command| match command.message {
+        CommandMessage::Frequency(frequency, volume) => Some((frequency, volume)),
+        _ => None,
+    })
+}
+
+#[test]
+fn test_shake_on_unused_valve() {
+    let mut trumpet = playing_trumpet();
+    trumpet
+        .effects_mut()
+        .bind(Gesture::Combination(0b1000), Effect::Shake)
+        .unwrap();
+
+    let note = trumpet.frequency().unwrap().to_num::<f64>();
+    let overtone = trumpet.state.overtone().unwrap() as f64;
+    let partial_above = note * (overtone + 2.) / (overtone + 1.);
+
+    trumpet.tick(&[TrumpetEvent::ValveDown(Valve::Fourth)]);
+    assert_eq!(trumpet.effects().active(), Some(Effect::Shake));
+
+    // Alternates between the note and the partial above it
+    let mut frequencies = Vec::new();
+    for _ in 0..50 {
+        assert!(sent_note(&trumpet.tick(&[])).is_some());
+        frequencies.push(trumpet.frequency().unwrap().to_num::<f64>());
+    }
+    assert!(frequencies.iter().any(|&f| (f - note).abs() < 1.));
+    assert!(frequencies.iter().any(|&f| (f - partial_above).abs() < 1.));
+
+    trumpet.tick(&[TrumpetEvent::ValveUp(Valve::Fourth)]);
+    assert_eq!(trumpet.effects().active(), None);
+    assert!((trumpet.frequency().unwrap().to_num::<f64>() - note).abs() < 1.);
+}
+
+#[test]
+fn test_doit_ends_the_note() {
+    let mut trumpet = playing_trumpet();
+    let note = trumpet.frequency().unwrap();
+
+    trumpet.effects_mut().trigger(Effect::Doit);
+
+    let mut last = note;
+    let mut last_volume = U4F4::MAX;
+    loop {
+        let (_, volume) = sent_note(&trumpet.tick(&[])).unwrap();
+        if trumpet.effects().active() != Some(Effect::Doit) {
+            assert_eq!(volume, 0);
+            break;
+        }
+
+        let frequency = trumpet.frequency().unwrap();
+        assert!(frequency >= last);
+        assert!(volume <= last_volume);
+        last = frequency;
+        last_volume = volume;
+    }
+    assert!(last > note);
+
+    // Silent until the note is blown again
+    let (_, volume) = sent_note(&trumpet.tick(&[TrumpetEvent::BlowStrengthChange(
+        BlowStrength::from_num(0.6),
+    )]))
+    .unwrap();
+    assert_eq!(volume, 0);
+
+    trumpet.tick(&[TrumpetEvent::BlowUp]);
+    let (frequency, volume) = sent_note(&trumpet.tick(&[TrumpetEvent::BlowDown])).unwrap();
+    assert!(volume > 0);
+    assert_eq!(frequency, U12F4::wrapping_from_num(note));
+}
+
+#[test]
+fn test_fall_on_valve_flutter() {
+    // Bound by default, so three valve front-ends can play it
+    let mut trumpet = playing_trumpet();
+    assert!(trumpet.effects().bindings().contains(&EffectBinding {
+        gesture: Gesture::ValveFlutter(Valve::Second),
+        effect: Effect::Fall,
+    }));
+
+    // Two presses are a trill, the third starts the fall
+    for _ in 0..2 {
+        trumpet.tick(&[TrumpetEvent::ValveDown(Valve::Second)]);
+        trumpet.tick(&[TrumpetEvent::ValveUp(Valve::Second)]);
+    }
+    assert_eq!(trumpet.effects().active(), None);
+
+    let note = trumpet.frequency().unwrap();
+    trumpet.tick(&[TrumpetEvent::ValveDown(Valve::Second)]);
+    trumpet.tick(&[TrumpetEvent::ValveUp(Valve::Second)]);
+    assert_eq!(trumpet.effects().active(), Some(Effect::Fall));
+
+    for _ in 0..20 {
+        trumpet.tick(&[]);
+    }
+    assert!(trumpet.frequency().unwrap() < note);
+}
+
+#[test]
+fn test_slow_presses_do_not_flutter() {
+    let mut trumpet = playing_trumpet();
+
+    for _ in 0..3 {
+        trumpet.tick(&[TrumpetEvent::ValveDown(Valve::Second)]);
+        for _ in 0..20 {
+            trumpet.tick(&[]);
+        }
+        trumpet.tick(&[TrumpetEvent::ValveUp(Valve::Second)]);
+    }
+    assert_eq!(trumpet.effects().active(), None);
+}
+
+#[test]
+fn test_doit_is_timed() {
+    // Updated every millisecond the doit takes as long as every 10ms
+    let mut trumpet = playing_trumpet();
+    let speed = trumpet.effects().parameters(Effect::Doit).speed;
+    trumpet.effects_mut().trigger(Effect::Doit);
+
+    let start = trumpet.time().unwrap();
+    let mut now = start;
+    while trumpet.effects().active() == Some(Effect::Doit) {
+        now += 1;
+        trumpet.update(&[], now);
+    }
+
+    let expected = (1000. / speed.to_num::<f64>()) as u32;
+    assert!((now - start).abs_diff(expected) <= 2, "{}ms", now - start);
+}
+
+#[test]
+fn test_flutter_tongue() {
+    let mut trumpet = playing_trumpet();
+    let parameters = trumpet.effects().parameters(Effect::Flutter);
+
+    trumpet.effects_mut().trigger(Effect::Flutter);
+    let commands = trumpet.tick(&[]);
+    assert!(commands.len() > 1);
+
+    let vibrato = trumpet.vibrato();
+    assert_eq!(vibrato.kind, VibratoKind::Breath);
+    assert_eq!(vibrato.rate, parameters.speed);
+    assert_eq!(vibrato.depth, parameters.depth);
+
+    // Ends with the note and restores the vibrato
+    trumpet.tick(&[TrumpetEvent::BlowUp]);
+    assert_eq!(trumpet.vibrato().kind, VibratoKind::Lip);
+}
+
+#[test]
+fn test_binding_limit() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.effects_mut().unbind_all();
+    for _ in 0..MAX_BINDINGS {
+        trumpet
+            .effects_mut()
+            .bind(Gesture::Combination(0b1000), Effect::Shake)
+            .unwrap();
+    }
+
+    assert_eq!(
+        trumpet
+            .effects_mut()
+            .bind(Gesture::Combination(0b1000), Effect::Shake),
+        Err(EffectError::TooManyBindings)
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::{interface, Disconnected, Full};
+use fixed::types::U8F8;
+use rytmos_synth::commands::{Command, CommandMessage};
+use trumpet_synth::{
+    interface::InterfaceError,
+    io::Input,
+    trumpet::{Embouchure, TrumpetSetting},
+    vibrato::VibratoSettings,
+};
+
+fn frequencies(written: &[u32]) -> Vec<u32> {
+    written
+        .iter()
+        .filter_map(|&value| match Command::deserialize(value)?.message {
+            CommandMessage::Frequency(frequency, _) => Some(frequency.to_bits() as u32),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_flaky_read_is_retried() {
+    let (mut interface, readings) = interface();
+    readings.borrow_mut().embouchure = 0.5;
+    readings.borrow_mut().embouchure_failures = 2;
+
+    assert_eq!(interface.run(), Ok(()));
+    assert!(interface.input_faults().is_empty());
+    assert_eq!(
+        interface.input_state().embouchure,
+        Embouchure::from_num(0.5)
+    );
+}
+
+#[test]
+fn test_disconnected_pot_holds() {
+    let (mut interface, readings) = interface();
+    readings.borrow_mut().embouchure = 0.5;
+    readings.borrow_mut().blow = true;
+    interface.run().unwrap();
+    interface.run().unwrap();
+    let frequency = interface.trumpet().frequency();
+    assert!(frequency.is_some());
+
+    // The note keeps sounding on the last reading while the pot is flagged
+    readings.borrow_mut().embouchure_failures = u32::MAX;
+    for _ in 0..10 {
+        let Err(InterfaceError::Input(error)) = interface.run() else {
+            panic!("the disconnected pot is reported");
+        };
+        assert_eq!(error.input, Input::Embouchure);
+        assert_eq!(error.error, Disconnected);
+    }
+    assert_eq!(interface.input_faults(), [Input::Embouchure]);
+    assert_eq!(interface.trumpet().frequency(), frequency);
+
+    readings.borrow_mut().embouchure_failures = 0;
+    assert_eq!(interface.run(), Ok(()));
+    assert!(interface.input_faults().is_empty());
+}
+
+#[test]
+fn test_refused_commands_are_resent() {
+    let (mut interface, readings) = interface();
+    readings.borrow_mut().refused = true;
+    readings.borrow_mut().embouchure = 0.5;
+    readings.borrow_mut().blow = true;
+
+    assert_eq!(interface.run(), Err(InterfaceError::Fifo(Full)));
+    let frequency = interface.trumpet().frequency().unwrap();
+
+    // Once the FIFO takes commands again the note starts
+    readings.borrow_mut().refused = false;
+    assert_eq!(interface.run(), Ok(()));
+    let written = frequencies(&readings.borrow().written);
+    assert_eq!(written.first(), Some(&(frequency.to_bits() >> 4)));
+    assert_eq!(interface.dropped(), 0);
+
+    // Refused for long, only the latest frequency is kept but no setting
+    // is lost
+    readings.borrow_mut().refused = true;
+    interface.configure(TrumpetSetting::Vibrato(VibratoSettings {
+        rate: U8F8::from_num(8),
+        ..Default::default()
+    }));
+    for embouchure in 0..100 {
+        readings.borrow_mut().embouchure = 0.3 + embouchure as f64 * 0.004;
+        interface.run().ok();
+    }
+    assert_eq!(interface.dropped(), 0);
+
+    readings.borrow_mut().refused = false;
+    readings.borrow_mut().written.clear();
+    interface.run().unwrap();
+    let frequency = interface.trumpet().frequency().unwrap();
+    let written = readings.borrow().written.clone();
+    assert_eq!(frequencies(&written), [frequency.to_bits() >> 4]);
+    assert!(written.iter().any(|&value| matches!(
+        Command::deserialize(value).unwrap().message,
+        CommandMessage::Reconfigure(_)
+    )));
+}
@@ -0,0 +1,45 @@
+use trumpet_synth::{fingering, trumpet::BFLAT_TRUMPET, tuning::TrumpetTuning};
+
+#[test]
+fn print_fingering_chart() {
+    let tuning = TrumpetTuning::default();
+    let chart = fingering::chart(&BFLAT_TRUMPET, &tuning);
+
+    for note in chart.iter() {
+        println!("{note}");
+    }
+
+    for note in chart.iter() {
+        assert!(!note.fingerings.is_empty());
+        for fingering in note.fingerings.iter() {
+            assert!(fingering.cents.abs() <= 50);
+        }
+    }
+}
+
+#[test]
+fn test_primary_fingerings() {
+    let tuning = TrumpetTuning::default();
+    let primary = |midi| {
+        *fingering::fingerings(&BFLAT_TRUMPET, &tuning, midi)
+            .unwrap()
+            .primary()
+            .unwrap()
+    };
+
+    // The open notes of the 4th and 6th partial
+    let c = primary(72);
+    assert_eq!((c.valves, c.overtone), (0b000, 1));
+    let g = primary(79);
+    assert_eq!((g.valves, g.overtone), (0b000, 2));
+    assert_eq!((c.partial(), g.partial()), (4, 6));
+    assert!(c.to_string().starts_with("0 (partial 4, "));
+
+    // C# is the lowest note of the 6th partial, with all valves down
+    let c_sharp = primary(73);
+    assert_eq!((c_sharp.valves, c_sharp.overtone), (0b111, 2));
+
+    // Written G5 can also be played on the 8th partial with 1-3
+    let g = fingering::fingerings(&BFLAT_TRUMPET, &tuning, 79).unwrap();
+    assert!(!g.alternates().is_empty());
+}
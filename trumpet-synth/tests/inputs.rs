@@ -0,0 +1,380 @@
+mod common;
+
+use std::{cell::Cell, convert::Infallible, rc::Rc};
+
+use common::{SharedClock, SharedReadings, TestClock, TestInputs};
+use fixed::types::{U0F16, U8F8};
+use trumpet_synth::{
+    analog::{AnalogChannel, ChannelSettings, Smoothing},
+    environment::Celsius,
+    interface::{TrumpetEvent, TrumpetInputs, ValveMode, MAX_EVENTS},
+    io::{Inputs, Millis},
+    trumpet::{BlowStrength, Embouchure, SlideExtension, Valve, ValvePosition},
+};
+
+/// Deterministic noise in -1..1.
+struct Noise(u32);
+
+impl Noise {
+    fn next_sample(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f64 / u32::MAX as f64 * 2. - 1.
+    }
+}
+
+const ONE_EURO: Smoothing = Smoothing::OneEuro {
+    min_cutoff: U8F8::ONE,
+    beta: U8F8::lit("0.5"),
+};
+
+fn inputs(settings: ChannelSettings) -> (TrumpetInputs<TestInputs, TestClock>, SharedReadings) {
+    let readings = SharedReadings::default();
+    let mut inputs = TrumpetInputs::new(TestInputs(Rc::clone(&readings)), TestClock::default(), 0);
+    inputs.set_conditioning(AnalogChannel::Embouchure, settings);
+
+    (inputs, readings)
+}
+
+/// Feeds the signal and returns the embouchure events sent.
+fn embouchure_events(
+    settings: ChannelSettings,
+    signal: impl Iterator<Item = f64>,
+) -> std::vec::Vec<Embouchure> {
+    let (mut inputs, readings) = inputs(settings);
+
+    let mut events = vec![];
+    for value in signal {
+        readings.borrow_mut().embouchure = value;
+        inputs.update_events().unwrap();
+        events.extend(inputs.events().iter().filter_map(|event| match event {
+            TrumpetEvent::EmbouchureChange(embouchure) => Some(*embouchure),
+            _ => None,
+        }));
+    }
+
+    events
+}
+
+/// A pot at rest at 0.5 with up to 4 steps of noise on a 12 bit ADC.
+fn noisy_rest(updates: usize) -> impl Iterator<Item = f64> {
+    let mut noise = Noise(0x1234_5678);
+    (0..updates).map(move |_| 0.5 + noise.next_sample() * 4. / 4096.)
+}
+
+#[test]
+fn test_noise_is_filtered() {
+    let unfiltered = embouchure_events(
+        ChannelSettings {
+            smoothing: Smoothing::None,
+            threshold: U0F16::ZERO,
+        },
+        noisy_rest(1000),
+    );
+    assert!(unfiltered.len() > 900, "{} events", unfiltered.len());
+
+    // The default threshold alone lets the noise through
+    let threshold = embouchure_events(ChannelSettings::DEFAULT, noisy_rest(1000));
+    assert!(threshold.len() > 500, "{} events", threshold.len());
+
+    for smoothing in [Smoothing::Exponential(U0F16::lit("0.05")), ONE_EURO] {
+        let events = embouchure_events(
+            ChannelSettings {
+                smoothing,
+                threshold: ChannelSettings::DEFAULT_THRESHOLD,
+            },
+            noisy_rest(1000),
+        );
+
+        // Settling on the resting position, then quiet
+        assert!(events.len() < 10, "{smoothing:?}: {} events", events.len());
+        let last = events.last().unwrap().to_num::<f64>();
+        assert!((last - 0.5).abs() < 0.002, "{smoothing:?}: {last}");
+    }
+}
+
+#[test]
+fn test_slow_bend_is_followed() {
+    // Half a second per 1% of travel, a slow lip bend
+    let mut noise = Noise(0x8765_4321);
+    let signal =
+        (0..1000).map(move |update| 0.5 + update as f64 * 0.0002 + noise.next_sample() / 4096.);
+
+    let events = embouchure_events(
+        ChannelSettings {
+            smoothing: ONE_EURO,
+            threshold: ChannelSettings::DEFAULT_THRESHOLD,
+        },
+        signal,
+    );
+
+    assert!(events.len() > 100, "{} events", events.len());
+    assert!(events.windows(2).all(|pair| pair[0] <= pair[1]));
+    let last = events.last().unwrap().to_num::<f64>();
+    assert!((last - 0.7).abs() < 0.01, "{last}");
+}
+
+#[test]
+fn test_one_euro_follows_steps() {
+    let signal = (0..100).map(|update| if update < 50 { 0.2 } else { 0.8 });
+    let (mut inputs, readings) = inputs(ChannelSettings {
+        smoothing: ONE_EURO,
+        threshold: ChannelSettings::DEFAULT_THRESHOLD,
+    });
+    let mut exponential =
+        TrumpetInputs::new(TestInputs(Rc::clone(&readings)), TestClock::default(), 0);
+    exponential.set_conditioning(
+        AnalogChannel::Embouchure,
+        ChannelSettings {
+            smoothing: Smoothing::Exponential(U0F16::lit("0.05")),
+            threshold: ChannelSettings::DEFAULT_THRESHOLD,
+        },
+    );
+
+    for (update, value) in signal.enumerate() {
+        readings.borrow_mut().embouchure = value;
+        inputs.update_events().unwrap();
+        exponential.update_events().unwrap();
+
+        // A fast movement raises the cutoff, so the one euro filter catches
+        // up much sooner than a moving average smoothing as much at rest
+        if update == 60 {
+            assert!(inputs.state().embouchure > Embouchure::lit("0.7"));
+            assert!(exponential.state().embouchure < Embouchure::lit("0.6"));
+        }
+    }
+
+    assert!(inputs.state().embouchure.abs_diff(Embouchure::lit("0.8")) < Embouchure::lit("0.01"));
+}
+
+#[test]
+fn test_one_euro_follows_time() {
+    // However often the pot is read, a step is followed in the same time
+    let follow = |interval: Millis, reads: usize| {
+        let readings = SharedReadings::default();
+        let time = Rc::new(Cell::new(0));
+        let mut inputs = TrumpetInputs::new(
+            TestInputs(Rc::clone(&readings)),
+            SharedClock(Rc::clone(&time)),
+            0,
+        );
+        inputs.set_conditioning(
+            AnalogChannel::Embouchure,
+            ChannelSettings {
+                smoothing: ONE_EURO,
+                threshold: U0F16::ZERO,
+            },
+        );
+
+        for millis in (0..=300).step_by(interval as usize) {
+            readings.borrow_mut().embouchure = if millis < 100 { 0.2 } else { 0.8 };
+            time.set(millis);
+            for _ in 0..reads {
+                inputs.update_events().unwrap();
+            }
+
+            // Reading more often does not settle at once
+            if millis == 110 {
+                assert!(inputs.state().embouchure < Embouchure::lit("0.7"));
+            }
+        }
+
+        inputs.state().embouchure
+    };
+
+    for (interval, reads) in [(1, 5), (10, 1)] {
+        let settled = follow(interval, reads);
+        assert!(settled.abs_diff(Embouchure::lit("0.8")) < Embouchure::lit("0.01"));
+    }
+}
+
+#[test]
+fn test_ends_are_reached() {
+    let events = embouchure_events(
+        ChannelSettings {
+            smoothing: Smoothing::None,
+            threshold: U0F16::lit("0.1"),
+        },
+        [0.5, 0.95, 1.0, 0.45, 0.42, 0.0].into_iter(),
+    );
+
+    // Within the threshold, but the ends are reported anyway
+    assert_eq!(
+        events,
+        [0.5, 0.95, 1.0, 0.45, 0.0].map(Embouchure::saturating_from_num)
+    );
+}
+
+#[test]
+fn test_debounce_is_timed() {
+    let readings = SharedReadings::default();
+    let time = Rc::new(Cell::new(0));
+    let mut inputs = TrumpetInputs::new(
+        TestInputs(Rc::clone(&readings)),
+        SharedClock(Rc::clone(&time)),
+        5,
+    );
+
+    // Pressed at 2ms, bouncing until it settles at 8ms
+    let bouncing = |millis: Millis| match millis {
+        0..=1 => false,
+        2..=7 => millis & 1 == 0,
+        _ => true,
+    };
+
+    // However often the inputs are read, the press counts 5ms after settling
+    let mut blow_downs = vec![];
+    for millis in 0..20 {
+        for _ in 0..10 {
+            time.set(millis);
+            readings.borrow_mut().blow = bouncing(millis);
+            inputs.update_events().unwrap();
+
+            blow_downs.extend(
+                inputs
+                    .timed_events()
+                    .filter(|timed| matches!(timed.event, TrumpetEvent::BlowDown))
+                    .map(|timed| timed.time),
+            );
+        }
+    }
+
+    assert_eq!(blow_downs, [13]);
+    assert!(inputs.state().blow);
+}
+
+#[test]
+fn test_events_are_timestamped() {
+    let (mut inputs, readings) = inputs(ChannelSettings::DEFAULT);
+
+    let mut times = vec![];
+    for value in [0.1, 0.1, 0.5, 0.5, 0.9] {
+        readings.borrow_mut().embouchure = value;
+        inputs.update_events().unwrap();
+        times.extend(inputs.timed_events().map(|timed| timed.time));
+    }
+
+    assert_eq!(times, [10, 30, 50]);
+    assert_eq!(inputs.time(), 50);
+}
+
+#[test]
+fn test_temperature_is_averaged() {
+    let readings = SharedReadings::default();
+    let time = Rc::new(Cell::new(0));
+    let mut inputs = TrumpetInputs::new(
+        TestInputs(Rc::clone(&readings)),
+        SharedClock(Rc::clone(&time)),
+        0,
+    );
+
+    let mut sent = vec![];
+    let mut noise = Noise(0x0bad_cafe);
+    for millis in 0..10_000 {
+        // Read in a tight loop, a degree of noise and a step to 25° after 5s
+        let room = if millis < 5000 { 20. } else { 25. };
+        time.set(millis);
+        readings.borrow_mut().temperature = Some(room + noise.next_sample());
+        inputs.update_events().unwrap();
+
+        sent.extend(inputs.timed_events().filter_map(|timed| match timed.event {
+            TrumpetEvent::TemperatureChange(temperature) => {
+                Some((timed.time, temperature.to_num::<f64>()))
+            }
+            _ => None,
+        }));
+    }
+
+    // The first reading, then the step once a whole interval read it
+    assert_eq!(sent.len(), 2, "{sent:?}");
+    assert!((sent[0].1 - 20.).abs() <= 1.);
+    assert!(
+        sent[1].0 <= 7000 && (sent[1].1 - 25.).abs() < 0.2,
+        "{sent:?}"
+    );
+}
+
+/// Every input jumps to a random reading on every update.
+struct RandomInputs(Noise);
+
+impl RandomInputs {
+    fn reading(&mut self) -> U0F16 {
+        U0F16::saturating_from_num((self.0.next_sample() + 1.) / 2.)
+    }
+}
+
+impl Inputs for RandomInputs {
+    type Error = Infallible;
+
+    fn valve(&mut self, _valve: Valve) -> Result<bool, Infallible> {
+        Ok(self.0.next_sample() > 0.)
+    }
+
+    fn blow(&mut self) -> Result<bool, Infallible> {
+        Ok(self.0.next_sample() > 0.)
+    }
+
+    fn embouchure(&mut self) -> Result<Embouchure, Infallible> {
+        Ok(self.reading())
+    }
+
+    fn blowstrength(&mut self) -> Result<BlowStrength, Infallible> {
+        Ok(self.reading())
+    }
+
+    fn slide(&mut self, _valve: Valve) -> Result<SlideExtension, Infallible> {
+        Ok(self.reading())
+    }
+
+    fn valve_position(&mut self, _valve: Valve) -> Result<ValvePosition, Infallible> {
+        Ok(self.reading())
+    }
+
+    fn temperature(&mut self) -> Result<Option<Celsius>, Infallible> {
+        Ok(Some(Celsius::saturating_from_num(
+            self.0.next_sample() * 40.,
+        )))
+    }
+
+    fn vibrato(&mut self) -> Result<Option<U0F16>, Infallible> {
+        Ok(Some(self.reading()))
+    }
+}
+
+#[test]
+fn test_events_never_overflow() {
+    for valve_mode in [ValveMode::Digital, ValveMode::Analog] {
+        let mut inputs =
+            TrumpetInputs::new(RandomInputs(Noise(0xdead_beef)), TestClock::default(), 0);
+        inputs.set_valve_mode(valve_mode);
+
+        let mut most_events = 0;
+        for _ in 0..10000 {
+            inputs.update_events().unwrap();
+            most_events = most_events.max(inputs.events().len());
+        }
+
+        assert!(most_events <= MAX_EVENTS);
+        assert_eq!(inputs.dropped_events(), 0, "{valve_mode:?}");
+    }
+}
+
+#[test]
+fn test_analog_events_supersede() {
+    let embouchure = TrumpetEvent::EmbouchureChange(Embouchure::ZERO);
+    let first_slide = TrumpetEvent::SlideChange(Valve::First, SlideExtension::ZERO);
+    let third_slide = TrumpetEvent::SlideChange(Valve::Third, SlideExtension::MAX);
+
+    assert!(embouchure.supersedes(&TrumpetEvent::EmbouchureChange(Embouchure::MAX)));
+    assert!(first_slide.supersedes(&TrumpetEvent::SlideChange(
+        Valve::First,
+        SlideExtension::MAX
+    )));
+    assert!(!first_slide.supersedes(&third_slide));
+    assert!(!embouchure.supersedes(&first_slide));
+
+    // Buttons are never coalesced, every press counts
+    let valve_down = TrumpetEvent::ValveDown(Valve::First);
+    assert!(!valve_down.supersedes(&valve_down));
+}
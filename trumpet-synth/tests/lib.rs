@@ -1,4 +1,4 @@
-use common::debouncer::Debouncer;
+use trumpet_synth::debounce::Debouncer;
 
 #[test]
 fn debounce_test() {
@@ -6,10 +6,21 @@ fn debounce_test() {
 
     let states = [false, true, false, false, false];
 
-    for &state in states.iter() {
-        debouncer.update(state);
-        println!("{:?}", debouncer.is_high());
-        println!("{:?}", debouncer,);
-        println!("{:?}\n", debouncer.is_high());
+    for (time, &state) in states.iter().enumerate() {
+        debouncer.update(state, time as u32);
+        assert_eq!(debouncer.is_high(), Some(state));
     }
+
+    let mut debouncer = Debouncer::new(10);
+    debouncer.update(true, 0);
+    assert_eq!(debouncer.is_high(), None);
+    debouncer.update(true, 9);
+    assert_eq!(debouncer.is_high(), None);
+    debouncer.update(true, 10);
+    assert_eq!(debouncer.is_high(), Some(true));
+
+    // The clock wrapping around does not hold up the debouncer
+    debouncer.update(false, u32::MAX - 4);
+    debouncer.update(false, 5);
+    assert_eq!(debouncer.is_high(), Some(false));
 }
@@ -0,0 +1,122 @@
+mod common;
+
+use common::{Tick, UPDATE_INTERVAL};
+use trumpet_synth::{
+    interface::TrumpetEvent,
+    io::Millis,
+    lungs::Lungs,
+    trumpet::{BlowStrength, Embouchure, Trumpet, TrumpetSetting, BFLAT_TRUMPET},
+};
+
+fn start_note(trumpet: &mut Trumpet, embouchure: f64) {
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.99)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(embouchure)),
+    ]);
+}
+
+/// How long a note can be held before it falls silent.
+fn phrase_length(embouchure: f64) -> Millis {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.configure(TrumpetSetting::RealisticLungs(true));
+    start_note(&mut trumpet, embouchure);
+
+    let start = trumpet.time().unwrap();
+    while trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()) > 0 {
+        trumpet.tick(&[]);
+        assert!(
+            trumpet.time().unwrap() - start < 10 * Lungs::DEFAULT_CAPACITY,
+            "never runs out of air"
+        );
+    }
+
+    trumpet.time().unwrap() - start
+}
+
+#[test]
+fn test_running_out_of_air() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.configure(TrumpetSetting::RealisticLungs(true));
+    start_note(&mut trumpet, 0.25);
+
+    let full_volume = trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning());
+    let in_tune = trumpet.frequency().unwrap();
+
+    // Most of the breath plays steadily
+    for _ in 0..Lungs::DEFAULT_CAPACITY / 2 / UPDATE_INTERVAL {
+        assert!(trumpet.tick(&[]).is_empty());
+    }
+    assert_eq!(
+        trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()),
+        full_volume
+    );
+
+    // Then the tone fades and wavers, changes the synth has to hear about
+    let mut wavered = false;
+    let mut commands = 0;
+    while trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()) > 0 {
+        commands += trumpet.tick(&[]).len();
+        wavered |= trumpet.frequency().unwrap() != in_tune;
+    }
+    assert!(wavered);
+    assert!(commands > 0);
+
+    // Reading the inputs again within the same millisecond changes nothing
+    let now = trumpet.time().unwrap();
+    for _ in 0..10 {
+        assert!(trumpet.update(&[], now).is_empty());
+    }
+
+    // A breath brings the tone back
+    trumpet.tick(&[TrumpetEvent::BlowUp]);
+    for _ in 0..Lungs::DEFAULT_REFILL / UPDATE_INTERVAL {
+        trumpet.tick(&[]);
+    }
+    trumpet.tick(&[TrumpetEvent::BlowDown]);
+    assert_eq!(
+        trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()),
+        full_volume
+    );
+    assert_eq!(trumpet.frequency().unwrap(), in_tune);
+}
+
+#[test]
+fn test_high_notes_use_more_air() {
+    let low = phrase_length(0.25);
+    let high = phrase_length(0.65);
+    assert!(high < low);
+    assert!(low < Lungs::DEFAULT_CAPACITY);
+
+    // Without the lungs a note never runs out
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    start_note(&mut trumpet, 0.25);
+    for _ in 0..2 * Lungs::DEFAULT_CAPACITY / UPDATE_INTERVAL {
+        trumpet.tick(&[]);
+    }
+    assert!(trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()) > 0);
+}
+
+#[test]
+fn test_breath_lasts_as_long_in_a_tight_loop() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.configure(TrumpetSetting::RealisticLungs(true));
+    start_note(&mut trumpet, 0.25);
+
+    // Updated every millisecond and several times within it
+    let start = trumpet.time().unwrap();
+    let mut now = start;
+    while trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()) > 0 {
+        now += 1;
+        for _ in 0..3 {
+            trumpet.update(&[], now);
+        }
+    }
+
+    let tight = now - start;
+    let updates = phrase_length(0.25);
+    assert!(
+        tight.abs_diff(updates) < updates / 50,
+        "{tight}ms, {updates}ms"
+    );
+}
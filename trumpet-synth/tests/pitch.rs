@@ -0,0 +1,74 @@
+mod common;
+
+use common::Tick;
+use fixed::types::U24F8;
+use rytmos_engrave::staff::{Accidental, Note};
+use trumpet_synth::{
+    interface::TrumpetEvent,
+    pitch::{self, Cents, NotePitch},
+    trumpet::{BlowStrength, Embouchure, Trumpet, BFLAT_TRUMPET},
+};
+
+#[test]
+fn test_cents_to_ratio() {
+    let cases = [
+        (0., 1.),
+        (1200., 2.),
+        (-1200., 0.5),
+        (700., 1.4983),
+        (31., 1.0181),
+        (-31., 0.9822),
+        (2450., 4.1172),
+    ];
+
+    for (cents, expected) in cases {
+        let ratio: f64 = pitch::cents_to_ratio(Cents::from_num(cents)).to_num();
+        println!("{cents} cents => {ratio}");
+        assert!((ratio - expected).abs() < 0.0005);
+    }
+}
+
+#[test]
+fn test_ratio_to_cents() {
+    for cents in [-2400., -1200., -31., 0., 1., 700., 1200., 2450.] {
+        let ratio = pitch::cents_to_ratio(Cents::from_num(cents));
+        let roundtrip: f64 = pitch::ratio_to_cents(ratio).to_num();
+        println!("{cents} cents => {ratio} => {roundtrip} cents");
+        assert!((roundtrip - cents).abs() < 0.1);
+    }
+}
+
+#[test]
+fn test_nearest_note() {
+    let a = NotePitch::nearest(U24F8::from_num(440), 0).unwrap();
+    assert!(matches!(a.note, Note::A(Accidental::Natural, 4)));
+    assert!(a.cents.abs() < 0.1);
+
+    let sharp_bflat = NotePitch::nearest(U24F8::from_num(470), 0).unwrap();
+    assert!(matches!(sharp_bflat.note, Note::B(Accidental::Flat, 4)));
+    assert!(sharp_bflat.cents > 14 && sharp_bflat.cents < 15);
+
+    let low_c = NotePitch::nearest(U24F8::from_num(116.54), 2).unwrap();
+    assert!(matches!(low_c.note, Note::C(Accidental::Natural, 3)));
+    assert_eq!(low_c.midi, 48);
+
+    assert!(NotePitch::nearest(U24F8::ZERO, 0).is_none());
+}
+
+#[test]
+fn test_written_pitch() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    assert!(trumpet.concert_pitch().is_none());
+
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.25)),
+    ]);
+
+    let concert = trumpet.concert_pitch().unwrap();
+    let written = trumpet.written_pitch().unwrap();
+    println!("concert {concert}, written {written}");
+    assert_eq!(written.midi, concert.midi + 2);
+    assert_eq!(written.cents, concert.cents);
+}
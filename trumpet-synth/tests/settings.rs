@@ -0,0 +1,118 @@
+mod common;
+
+use common::interface;
+use fixed::types::U24F8;
+use trumpet_synth::{
+    io::Input,
+    pitch::Cents,
+    settings::{SettingsGesture, Storage, Stored},
+    trumpet::Valve,
+};
+
+#[derive(Default)]
+struct MemoryStorage {
+    stored: Option<Stored>,
+    saves: usize,
+}
+
+impl Storage for MemoryStorage {
+    fn load(&mut self) -> Option<Stored> {
+        self.stored
+    }
+
+    fn save(&mut self, stored: Stored) {
+        self.stored = Some(stored);
+        self.saves += 1;
+    }
+}
+
+#[test]
+fn test_stored_settings_are_applied() {
+    let (mut interface, _) = interface();
+    let storage = MemoryStorage {
+        stored: Some(Stored {
+            reference_pitch: U24F8::from_num(442),
+            transpose: Cents::from_num(-200),
+            calibration: None,
+        }),
+        saves: 0,
+    };
+
+    SettingsGesture::load(storage, &mut interface);
+    interface.run().unwrap();
+    assert_eq!(interface.trumpet().reference_pitch(), U24F8::from_num(442));
+    assert_eq!(interface.trumpet().transpose(), Cents::from_num(-200));
+}
+
+#[test]
+fn test_gesture_with_faulty_input() {
+    let (mut interface, readings) = interface();
+    let mut settings = SettingsGesture::load(MemoryStorage::default(), &mut interface);
+    readings.borrow_mut().slide_disconnected = true;
+
+    // All valves down and the pot squeezed selects the lowest reference pitch
+    readings.borrow_mut().valves = [true; 3];
+    readings.borrow_mut().blowstrength = 1.0;
+    readings.borrow_mut().embouchure = 0.0;
+    assert!(interface.run().is_err());
+    assert_eq!(interface.input_faults(), [Input::Slide(Valve::First)]);
+    assert_eq!(settings.update(&mut interface), Some((0, 6)));
+    assert_eq!(settings.storage().saves, 0);
+
+    // Releasing the combination saves it
+    readings.borrow_mut().valves = [false; 3];
+    readings.borrow_mut().blowstrength = 0.0;
+    assert!(interface.run().is_err());
+    assert_eq!(settings.update(&mut interface), None);
+    assert_eq!(settings.storage().saves, 1);
+    assert_eq!(
+        settings
+            .storage()
+            .stored
+            .map(|stored| stored.reference_pitch),
+        Some(U24F8::from_num(415))
+    );
+    assert_eq!(interface.trumpet().reference_pitch(), U24F8::from_num(415));
+}
+
+#[test]
+fn test_calibration_is_saved() {
+    let (mut interface, readings) = interface();
+    let mut settings = SettingsGesture::load(MemoryStorage::default(), &mut interface);
+    readings.borrow_mut().slide_disconnected = true;
+    readings.borrow_mut().embouchure = 0.3;
+    readings.borrow_mut().blowstrength = 0.1;
+    readings.borrow_mut().valves = [true; 3];
+
+    for _ in 0..1000 {
+        interface.run().ok();
+        settings.update(&mut interface);
+        if interface.calibrating() {
+            break;
+        }
+    }
+    assert!(interface.calibrating());
+
+    // Squeezing the pot in the sweep doesn't select settings
+    for (embouchure, blowstrength) in [(0.3, 0.1), (0.15, 0.3), (0.8, 1.0), (0.3, 0.1)] {
+        readings.borrow_mut().embouchure = embouchure;
+        readings.borrow_mut().blowstrength = blowstrength;
+        interface.run().ok();
+        assert_eq!(settings.update(&mut interface), None);
+    }
+    assert_eq!(settings.storage().saves, 0);
+
+    readings.borrow_mut().blow = true;
+    interface.run().ok();
+    assert!(!interface.calibrating());
+    settings.update(&mut interface);
+    assert_eq!(settings.storage().saves, 1);
+    assert_eq!(
+        settings
+            .storage()
+            .stored
+            .and_then(|stored| stored.calibration),
+        Some(*interface.calibration())
+    );
+    assert_eq!(interface.trumpet().reference_pitch(), U24F8::from_num(440));
+}
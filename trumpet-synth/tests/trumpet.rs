@@ -1,16 +1,30 @@
+mod common;
+
+use common::{Tick, UPDATE_INTERVAL};
+use fixed::types::{U0F16, U12F4, U24F8};
 use plotters::prelude::*;
+use rytmos_synth::commands::CommandMessage;
 use std::{error::Error, process::Command};
 
 use trumpet_synth::{
+    articulation::{Articulation, ArticulationDetector},
+    assist::PitchAssist,
+    environment::{Celsius, Environment, Humidity},
     interface::TrumpetEvent,
-    trumpet::{BlowStrength, Embouchure, Trumpet, BFLAT_TRUMPET},
+    pitch::{Cents, NotePitch},
+    presets::{self, PRESETS},
+    trumpet::{
+        BlowStrength, Embouchure, InstrumentKind, SlideExtension, Trumpet, TrumpetDefinition,
+        TrumpetSetting, Valve, ValvePosition, BFLAT_TRUMPET, MAX_VALVES,
+    },
+    tuning::{TrumpetTuning, TuningError},
 };
 
 #[test]
 fn test_trumpet_frequencies() {
     let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
 
-    trumpet.update(&[
+    trumpet.tick(&[
         TrumpetEvent::BlowDown,
         TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.8)),
         TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.23)),
@@ -19,15 +33,582 @@ fn test_trumpet_frequencies() {
     dbg!(
         trumpet.state.overtone(),
         trumpet.state.tube_length(&BFLAT_TRUMPET),
-        trumpet.state.volume(),
+        trumpet.state.volume(trumpet.definition(), trumpet.tuning()),
         trumpet.frequency(),
     );
 }
 
+#[test]
+fn test_tuning_validation() {
+    let map = TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP;
+    let bendability = TrumpetTuning::DEFAULT_BENDABILITY_PER_OVERTONE;
+
+    assert!(TrumpetTuning::from_maps(&map, &bendability).is_ok());
+    assert_eq!(
+        TrumpetTuning::from_maps(&map, &bendability[1..]).err(),
+        Some(TuningError::LengthMismatch)
+    );
+
+    let mut reversed = map;
+    reversed.reverse();
+    assert_eq!(
+        TrumpetTuning::from_maps(&reversed, &bendability).err(),
+        Some(TuningError::NotMonotonic)
+    );
+
+    let mut tuning = TrumpetTuning::default();
+    assert!(tuning.set_maps(&reversed, &bendability).is_err());
+    assert_eq!(tuning.embouchure_to_overtone_map(), &map);
+}
+
+#[test]
+fn test_preset_tunings_are_valid() {
+    for preset in PRESETS.iter() {
+        assert_eq!(
+            TrumpetTuning::validate(
+                preset.embouchure_to_overtone_map,
+                preset.bendability_per_overtone
+            ),
+            Ok(()),
+            "{}",
+            preset.name
+        );
+    }
+}
+
+#[test]
+fn test_preset_frequencies() {
+    // Concert pitch of the open horn's overtone 0 in the key of each preset
+    let keys = [
+        ("bb_trumpet", 58),
+        ("c_trumpet", 60),
+        ("d_trumpet", 62),
+        ("eb_trumpet", 63),
+        ("bb_piccolo", 70),
+        ("a_piccolo", 69),
+        ("cornet", 58),
+        ("flugelhorn", 58),
+        ("bugle", 55),
+        ("compensating_flugelhorn", 58),
+        ("trombone", 46),
+        ("natural_trumpet", 50),
+    ];
+    assert_eq!(keys.len(), PRESETS.len());
+
+    for (index, preset) in PRESETS.iter().enumerate() {
+        assert_eq!(presets::index_of(preset.name), Some(index));
+        assert!(presets::by_index(index).is_some());
+
+        // The lowest overtone the map reaches, played in the middle of its slot
+        let map = preset.embouchure_to_overtone_map;
+        let overtone = map.windows(2).position(|w| w[0] < w[1]).unwrap();
+        let mut trumpet = preset.trumpet();
+        trumpet.tick(&[
+            TrumpetEvent::EmbouchureChange(map[overtone] + (map[overtone + 1] - map[overtone]) / 2),
+            TrumpetEvent::BlowDown,
+            TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        ]);
+        assert_eq!(trumpet.state.overtone(), Some(overtone as u8));
+
+        let partial = trumpet.frequency().unwrap();
+        let open = partial / U24F8::from_num(overtone + 1);
+        let pitch = NotePitch::nearest(open, 0).unwrap();
+        let (name, midi) = keys[index];
+        assert_eq!((preset.name, pitch.midi), (name, midi));
+        assert!(pitch.cents.abs() < 30, "{name}: {}", pitch.cents);
+    }
+}
+
+#[test]
+fn test_compensating_valves() {
+    let flugelhorn = presets::by_name("compensating_flugelhorn").unwrap();
+    let mut trumpet = flugelhorn.trumpet();
+
+    trumpet.tick(&[TrumpetEvent::ValveDown(Valve::First)]);
+    let first = trumpet.state.tube_length(&flugelhorn.definition);
+    trumpet.tick(&[TrumpetEvent::ValveDown(Valve::Fourth)]);
+    let first_and_fourth = trumpet.state.tube_length(&flugelhorn.definition);
+
+    let tubes = flugelhorn.definition.valve_tubes;
+    assert_eq!(
+        first,
+        U24F8::from(flugelhorn.definition.main_tube + tubes[0])
+    );
+    assert!(first_and_fourth > first + U24F8::from(tubes[3]));
+
+    let bugle = presets::by_name("bugle").unwrap();
+    assert_eq!(
+        bugle.definition.tube_length(0b1111),
+        U24F8::from(bugle.definition.main_tube)
+    );
+}
+
+#[test]
+fn test_valve_count_is_limited() {
+    for valves in [0, 3, MAX_VALVES, MAX_VALVES + 1, 16, 64, usize::MAX] {
+        let definition = TrumpetDefinition {
+            valves,
+            ..BFLAT_TRUMPET
+        };
+
+        let expected = (1u16 << valves.min(MAX_VALVES)) - 1;
+        assert_eq!(definition.valve_mask() as u16, expected, "{valves} valves");
+    }
+}
+
+#[test]
+fn test_slides_lower_pitch() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.1)),
+        TrumpetEvent::ValveDown(Valve::First),
+        TrumpetEvent::ValveDown(Valve::Third),
+    ]);
+    let low_d = trumpet.frequency().unwrap();
+
+    trumpet.tick(&[TrumpetEvent::SlideChange(
+        Valve::Third,
+        SlideExtension::from_num(0.5),
+    )]);
+    let low_d_slide_out = trumpet.frequency().unwrap();
+    assert!(low_d_slide_out < low_d);
+
+    // Slides are out of the air path when their valve is up
+    trumpet.tick(&[TrumpetEvent::ValveUp(Valve::Third)]);
+    let slide_unused = trumpet.frequency().unwrap();
+    trumpet.tick(&[TrumpetEvent::SlideChange(
+        Valve::Third,
+        SlideExtension::ZERO,
+    )]);
+    assert_eq!(slide_unused, trumpet.frequency().unwrap());
+}
+
+#[test]
+fn test_trombone_slide() {
+    let trombone = presets::by_name("trombone").unwrap();
+    let mut trumpet = trombone.trumpet();
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.1)),
+    ]);
+    let first_position = trumpet.frequency().unwrap().to_num::<f64>();
+    let semitones_down = |trumpet: &Trumpet| {
+        (first_position / trumpet.frequency().unwrap().to_num::<f64>()).log2() * 12.
+    };
+
+    // Fully out is seventh position, a tritone lower on the same partial
+    trumpet.tick(&[TrumpetEvent::SlideChange(Valve::First, SlideExtension::MAX)]);
+    assert!((semitones_down(&trumpet) - 6.).abs() < 0.05);
+
+    // Valves are not part of the air path
+    let seventh_position = trumpet.frequency();
+    trumpet.tick(&[TrumpetEvent::ValveDown(Valve::First)]);
+    assert_eq!(trumpet.frequency(), seventh_position);
+
+    // Without detents the slide plays in between the positions
+    let fourth = trombone.definition.slide_position_length(3);
+    let seventh = trombone.definition.slide_position_length(6);
+    let near_fourth = SlideExtension::from_num(fourth / seventh + U24F8::from_num(0.02));
+    trumpet.tick(&[TrumpetEvent::SlideChange(Valve::First, near_fourth)]);
+    assert!((semitones_down(&trumpet) - 3.).abs() > 0.05);
+
+    let mut detents = trombone.definition;
+    detents.kind = InstrumentKind::Slide { detents: true };
+    trumpet.set_definition(detents);
+    trumpet.tick(&[]);
+    assert!((semitones_down(&trumpet) - 3.).abs() < 0.05);
+}
+
+#[test]
+fn test_partial_intonation() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+    ]);
+
+    // Plays a partial as players count it, overtone n is the partial 2(n + 1)
+    let map = TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP;
+    let mut open = |partial: usize| {
+        let overtone = partial / 2 - 1;
+        let center = map[overtone] + (map[overtone + 1] - map[overtone]) / 2;
+        trumpet.tick(&[TrumpetEvent::EmbouchureChange(center)]);
+        assert_eq!(trumpet.state.overtone(), Some(overtone as u8));
+        trumpet.frequency().unwrap().to_num::<f64>()
+    };
+    let cents = |from: f64, to: f64| 1200. * (to / from).log2();
+
+    // The 6th partial (written G5) is sharp of a pure fifth above the 4th
+    let fourth = open(4);
+    let sixth = open(6);
+    let sharp = cents(fourth, sixth) - cents(2., 3.);
+    assert!((sharp - 8.).abs() < 1., "{sharp}");
+
+    // The 14th partial (written Bb6) is flat of a tempered minor seventh above
+    // the 8th
+    let eighth = open(8);
+    let fourteenth = open(14);
+    let flat = cents(eighth, fourteenth) - 1000.;
+    assert!((flat + 31. + 12.).abs() < 1., "{flat}");
+}
+
+#[test]
+fn test_natural_trumpet() {
+    let natural = presets::by_name("natural_trumpet").unwrap();
+    let mut trumpet = natural.trumpet();
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+    ]);
+
+    // Every slot the map reaches plays the next pure harmonic
+    let map = natural.embouchure_to_overtone_map;
+    let mut fundamental = None;
+    for (overtone, window) in map.windows(2).enumerate().skip(1) {
+        let center = window[0] + (window[1] - window[0]) / 2;
+        trumpet.tick(&[TrumpetEvent::EmbouchureChange(center)]);
+        assert_eq!(trumpet.state.overtone(), Some(overtone as u8));
+
+        let partial = trumpet.frequency().unwrap().to_num::<f64>() / (overtone + 1) as f64;
+        let fundamental = *fundamental.get_or_insert(partial);
+        assert!((partial / fundamental - 1.).abs() < 0.01);
+    }
+
+    trumpet.tick(&[TrumpetEvent::ValveDown(Valve::Second)]);
+    assert_eq!(
+        trumpet.state.tube_length(&natural.definition),
+        U24F8::from(natural.definition.main_tube)
+    );
+}
+
+#[test]
+fn test_half_valve() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.25)),
+    ]);
+    let open = trumpet.frequency().unwrap();
+    let open_volume = trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning());
+
+    // Slurs to the next note and sends its frequency
+    let commands = trumpet.tick(&[TrumpetEvent::ValveDown(Valve::Second)]);
+    assert_eq!(commands.len(), 2);
+    let second = trumpet.frequency().unwrap();
+    assert_eq!(trumpet.state.choke(&BFLAT_TRUMPET), 0);
+
+    // Sends the choke along with the frequency once the valve is half pressed
+    let commands = trumpet.tick(&[TrumpetEvent::ValvePositionChange(
+        Valve::Second,
+        ValvePosition::from_num(0.5),
+    )]);
+    assert_eq!(commands.len(), 2);
+    let half = trumpet.frequency().unwrap();
+    assert!(second < half && half < open);
+    assert!(trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()) < open_volume);
+    assert!(trumpet.state.choke(&BFLAT_TRUMPET) > 0.9);
+
+    trumpet.tick(&[TrumpetEvent::ValveUp(Valve::Second)]);
+    assert_eq!(trumpet.frequency().unwrap(), open);
+    assert_eq!(trumpet.state.choke(&BFLAT_TRUMPET), 0);
+}
+
+#[test]
+fn test_pedal_and_false_tones() {
+    // Slot 0 is the 2nd partial and plays like any other once the map
+    // reaches it
+    let mut map = TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP;
+    map[1] = Embouchure::from_num(0.05);
+    let mut tuning = TrumpetTuning::default();
+    tuning
+        .set_maps(&map, &TrumpetTuning::DEFAULT_BENDABILITY_PER_OVERTONE)
+        .unwrap();
+
+    let mut trumpet = Trumpet::with_tuning(BFLAT_TRUMPET, tuning.clone());
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.03)),
+    ]);
+    assert_eq!(trumpet.state.overtone(), Some(0));
+    let low_c = trumpet.frequency().unwrap();
+    let low_c_volume = trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning());
+
+    // The default tuning has no pedal register
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(Embouchure::DELTA)]);
+    assert_eq!(trumpet.state.overtone(), Some(0));
+    assert_eq!(
+        trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()),
+        low_c_volume
+    );
+
+    let top = Embouchure::from_num(0.02);
+    tuning.set_pedal_register(
+        top,
+        TrumpetTuning::DEFAULT_PEDAL_VOLUME,
+        TrumpetTuning::DEFAULT_FALSE_TONE_RANGE,
+    );
+    trumpet.set_tuning(tuning);
+
+    // The pedal slots weakly: the lips hold low C just below its embouchure,
+    // and drop to the pedal half a slot band further down
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(top - Embouchure::DELTA)]);
+    assert_eq!(trumpet.frequency().unwrap(), low_c);
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.01))]);
+    assert_eq!(trumpet.state.overtone(), Some(0));
+    assert!(trumpet.frequency().unwrap() < low_c);
+    assert!(trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()) < low_c_volume);
+
+    // Once in the pedal the lips stay there just above its embouchure
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(top + Embouchure::DELTA)]);
+    let highest_false_tone = trumpet.frequency().unwrap();
+    assert!(highest_false_tone < low_c);
+
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(Embouchure::ZERO)]);
+    let pedal = trumpet.frequency().unwrap();
+
+    // The pedal is an octave below low C and the false tones span most of
+    // that octave
+    assert!((pedal.to_num::<f64>() / low_c.to_num::<f64>() - 0.5).abs() < 0.02);
+    assert!(highest_false_tone > pedal * U24F8::from_num(1.7));
+
+    // Tightening again brings back low C at full volume
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.03))]);
+    assert_eq!(trumpet.frequency().unwrap(), low_c);
+    assert_eq!(
+        trumpet.state.volume(&BFLAT_TRUMPET, trumpet.tuning()),
+        low_c_volume
+    );
+}
+
+#[test]
+fn test_preset_pedal() {
+    let preset = presets::by_name("bb_trumpet").unwrap();
+    let mut trumpet = preset.trumpet();
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.04)),
+    ]);
+    assert_eq!(trumpet.state.overtone(), Some(1));
+    let c = trumpet.frequency().unwrap();
+
+    // The loosest embouchure plays the pedal, two octaves below the open C
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(Embouchure::ZERO)]);
+    assert_eq!(trumpet.state.overtone(), Some(0));
+    let pedal = trumpet.frequency().unwrap();
+    assert!((pedal.to_num::<f64>() / c.to_num::<f64>() - 0.25).abs() < 0.01);
+}
+
+#[test]
+fn test_temperature_changes_tuning() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.25)),
+    ]);
+    let room = trumpet.frequency().unwrap();
+
+    trumpet.configure(TrumpetSetting::Temperature(Celsius::from_num(5)));
+    let cold = trumpet.frequency().unwrap();
+    assert!(cold < room);
+
+    trumpet.tick(&[TrumpetEvent::TemperatureChange(Celsius::from_num(30))]);
+    let warm = trumpet.frequency().unwrap();
+    assert!(warm > room);
+
+    // Roughly 3 cents per degree
+    let cents = 1200. * (warm.to_num::<f64>() / cold.to_num::<f64>()).log2();
+    assert!((cents - 75.).abs() < 10.);
+
+    trumpet.configure(TrumpetSetting::Temperature(Environment::ROOM.temperature));
+    trumpet.configure(TrumpetSetting::Humidity(Humidity::from_num(0.8)));
+    assert!(trumpet.frequency().unwrap() > room);
+}
+
+#[test]
+fn test_reference_pitch_and_transpose() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.25)),
+    ]);
+    let frequency = trumpet.frequency().unwrap().to_num::<f64>();
+    let pitch = trumpet.concert_pitch().unwrap();
+
+    trumpet.configure(TrumpetSetting::ReferencePitch(U24F8::from_num(415)));
+    let baroque = trumpet.frequency().unwrap().to_num::<f64>();
+    assert!((baroque / frequency - 415. / 440.).abs() < 0.001);
+
+    // The synth hears the new pitch on the next update, even without events
+    let sent = U12F4::wrapping_from_num(trumpet.frequency().unwrap());
+    let commands = trumpet.tick(&[]);
+    assert!(commands.iter().any(|command| matches!(
+        command.message,
+        CommandMessage::Frequency(frequency, _) if frequency == sent
+    )));
+    assert_eq!(trumpet.tick(&[]).len(), 0);
+
+    // Notes are named against the reference, so the horn stays in tune with itself
+    let baroque_pitch = trumpet.concert_pitch().unwrap();
+    assert_eq!(baroque_pitch.midi, pitch.midi);
+    assert!((baroque_pitch.cents - pitch.cents).abs() < 1);
+
+    trumpet.configure(TrumpetSetting::Transpose(Cents::from_num(-100)));
+    assert_eq!(trumpet.concert_pitch().unwrap().midi, pitch.midi - 1);
+    assert_eq!(trumpet.written_pitch().unwrap().midi, pitch.midi + 1);
+}
+
+#[test]
+fn test_articulation() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.25)),
+    ]);
+    assert_eq!(trumpet.last_articulation(), None);
+
+    trumpet.tick(&[TrumpetEvent::BlowDown]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::Attack));
+
+    trumpet.tick(&[TrumpetEvent::ValveDown(Valve::First)]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::Slur));
+
+    // Moving to another partial with the lips glides over to it
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.35))]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::LipSlur));
+
+    // A short break in the air is a tongued note
+    trumpet.tick(&[TrumpetEvent::BlowUp]);
+    assert_eq!(trumpet.last_articulation(), None);
+    for _ in 0..5 {
+        trumpet.tick(&[]);
+    }
+    trumpet.tick(&[TrumpetEvent::BlowDown]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::Tongue));
+
+    // Holding the same note sends no new articulation
+    let commands = trumpet.tick(&[TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(
+        0.55,
+    ))]);
+    assert_eq!(commands.len(), 1);
+
+    // A rest longer than the tongue gap starts a new phrase
+    trumpet.tick(&[TrumpetEvent::BlowUp]);
+    for _ in 0..ArticulationDetector::DEFAULT_TONGUE_GAP / UPDATE_INTERVAL + 1 {
+        trumpet.tick(&[]);
+    }
+    trumpet.tick(&[TrumpetEvent::BlowDown]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::Attack));
+
+    // Pushing the air as the note starts accents it
+    trumpet.tick(&[TrumpetEvent::BlowUp]);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.95)),
+    ]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::Accent));
+
+    // Also when the push is read shortly after the onset
+    trumpet.tick(&[TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(
+        0.5,
+    ))]);
+    trumpet.tick(&[TrumpetEvent::BlowUp]);
+    trumpet.tick(&[TrumpetEvent::BlowDown]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::Tongue));
+    trumpet.tick(&[TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(
+        0.95,
+    ))]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::Accent));
+
+    // Pushing later in the note is a crescendo
+    trumpet.tick(&[TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(
+        0.5,
+    ))]);
+    trumpet.tick(&[TrumpetEvent::BlowUp]);
+    trumpet.tick(&[TrumpetEvent::BlowDown]);
+    for _ in 0..ArticulationDetector::ACCENT_WINDOW / UPDATE_INTERVAL {
+        trumpet.tick(&[]);
+    }
+    trumpet.tick(&[TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(
+        0.95,
+    ))]);
+    assert_eq!(trumpet.last_articulation(), Some(Articulation::Tongue));
+}
+
+/// Sweeps the embouchure over the full range and records at which embouchure
+/// each new overtone starts sounding.
+fn overtone_transitions(trumpet: &mut Trumpet, rising: bool) -> Vec<(u8, f64)> {
+    let mut steps: Vec<u16> = (0..u16::MAX).step_by(1 << 4).collect();
+    if !rising {
+        steps.reverse();
+    }
+
+    let mut transitions = Vec::new();
+    let mut last_overtone = None;
+    for i in steps {
+        let embouchure = Embouchure::from_bits(i);
+        trumpet.tick(&[TrumpetEvent::EmbouchureChange(embouchure)]);
+
+        let overtone = trumpet.state.overtone();
+        if overtone != last_overtone {
+            if let Some(overtone) = overtone {
+                transitions.push((overtone, embouchure.to_num()));
+            }
+            last_overtone = overtone;
+        }
+    }
+
+    transitions
+}
+
+#[test]
+fn test_slot_hysteresis_sweeps() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+    ]);
+
+    let rising = overtone_transitions(&mut trumpet, true);
+    let falling = overtone_transitions(&mut trumpet, false);
+    println!("rising: {:?}\nfalling: {:?}", rising, falling);
+
+    let map = TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP;
+    for &(overtone, embouchure) in rising.iter().skip(1) {
+        // Jumping up only happens past the boundary of the new overtone
+        assert!(embouchure > map[overtone as usize].to_num::<f64>());
+    }
+
+    for &(overtone, embouchure) in falling.iter().skip(1) {
+        // Falling down only happens below the boundary of the overtone above
+        assert!(embouchure < map[overtone as usize + 1].to_num::<f64>());
+    }
+
+    // Noise around a boundary smaller than the hysteresis does not flip the note
+    let boundary = map[4];
+    trumpet.tick(&[TrumpetEvent::EmbouchureChange(
+        boundary + Embouchure::from_num(0.05),
+    )]);
+    let slot = trumpet.state.overtone();
+    for i in 0..100 {
+        let noise = Embouchure::from_bits((i * 37 % 400) as u16);
+        let embouchure = boundary - Embouchure::from_bits(200) + noise;
+        trumpet.tick(&[TrumpetEvent::EmbouchureChange(embouchure)]);
+        assert_eq!(trumpet.state.overtone(), slot);
+    }
+}
+
 #[test]
 fn plot_embouchure_to_frequency() {
     let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
-    trumpet.update(&[
+    trumpet.tick(&[
         TrumpetEvent::BlowDown,
         TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.9)),
     ]);
@@ -36,7 +617,7 @@ fn plot_embouchure_to_frequency() {
     for i in (0..u16::MAX).step_by(1 << 4) {
         let embouchure = Embouchure::from_bits(i);
 
-        trumpet.update(&[TrumpetEvent::EmbouchureChange(embouchure)]);
+        trumpet.tick(&[TrumpetEvent::EmbouchureChange(embouchure)]);
 
         let frequency = trumpet.frequency().map(|n| n.to_num()).unwrap_or(0.);
         let embouchure = embouchure.to_num();
@@ -86,3 +667,73 @@ fn plot(data: Vec<(f64, f64)>, y_label: &str, x_label: &str) -> Result<(), Box<d
 
     Ok(())
 }
+
+#[test]
+fn test_pitch_assist() {
+    let cents_off = |trumpet: &mut Trumpet, embouchure: f64| {
+        trumpet.tick(&[TrumpetEvent::EmbouchureChange(Embouchure::from_num(
+            embouchure,
+        ))]);
+        trumpet.concert_pitch().unwrap().cents.to_num::<f64>()
+    };
+
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.8)),
+    ]);
+
+    // Near the top of the slot the lips bend the note well out of tune
+    let raw = cents_off(&mut trumpet, 0.2);
+
+    trumpet.configure(TrumpetSetting::PitchAssist(PitchAssist {
+        strength: U0F16::MAX,
+        quantize: false,
+    }));
+    let straight = cents_off(&mut trumpet, 0.2);
+    assert!((raw - straight).abs() > 20.);
+
+    trumpet.configure(TrumpetSetting::PitchAssist(PitchAssist {
+        strength: U0F16::from_num(0.5),
+        quantize: false,
+    }));
+    let half = (cents_off(&mut trumpet, 0.2) - straight) / (raw - straight);
+    assert!(half > 0.3 && half < 0.7);
+
+    // Fully assisted every embouchure in every slot plays in tune
+    trumpet.configure(TrumpetSetting::PitchAssist(PitchAssist {
+        strength: U0F16::MAX,
+        quantize: true,
+    }));
+    for step in 1..90 {
+        let cents = cents_off(&mut trumpet, step as f64 / 100.);
+        assert!(cents.abs() < 2., "{} cents off", cents);
+    }
+}
+
+#[test]
+fn test_pitch_assist_holds_the_slot() {
+    let boundary = TrumpetTuning::DEFAULT_EMBOUCHURE_TO_OVERTONE_MAP[4];
+    let past_boundary = boundary + TrumpetTuning::DEFAULT_SLOT_HYSTERESIS * 2;
+
+    let overtone_after_crossing = |assist: PitchAssist| {
+        let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+        trumpet.configure(TrumpetSetting::PitchAssist(assist));
+        trumpet.tick(&[
+            TrumpetEvent::BlowDown,
+            TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+            TrumpetEvent::EmbouchureChange(boundary - Embouchure::from_num(0.02)),
+        ]);
+        trumpet.tick(&[TrumpetEvent::EmbouchureChange(past_boundary)]);
+        trumpet.state.overtone()
+    };
+
+    assert_eq!(overtone_after_crossing(PitchAssist::default()), Some(4));
+    assert_eq!(
+        overtone_after_crossing(PitchAssist {
+            strength: U0F16::MAX,
+            quantize: false,
+        }),
+        Some(3)
+    );
+}
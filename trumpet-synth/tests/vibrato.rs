@@ -0,0 +1,107 @@
+mod common;
+
+use common::{Tick, UPDATE_INTERVAL};
+use fixed::types::{U0F16, U8F8};
+use trumpet_synth::{
+    interface::TrumpetEvent,
+    synth::SAMPLE_RATE,
+    trumpet::{BlowStrength, Embouchure, Trumpet, TrumpetSetting, BFLAT_TRUMPET},
+    vibrato::{ShakeDetector, Vibrato, VibratoControl, VibratoSettings},
+};
+
+#[test]
+fn test_vibrato_delay_and_rate() {
+    let mut vibrato = Vibrato::new(VibratoSettings {
+        rate: U8F8::from_num(6),
+        depth: U0F16::from_num(0.5),
+        delay: 250,
+        ..Default::default()
+    });
+
+    let samples: Vec<f64> = (0..SAMPLE_RATE * 2)
+        .map(|_| vibrato.next().to_num::<f64>())
+        .collect();
+
+    let delay = SAMPLE_RATE as usize / 4;
+    assert!(samples[..delay].iter().all(|&sample| sample == 0.));
+
+    // Fades in over the first wobble and stays within the depth
+    let fade_end = delay + SAMPLE_RATE as usize / 6;
+    let peak = |range: &[f64]| range.iter().fold(0., |peak: f64, s| peak.max(s.abs()));
+    assert!(peak(&samples[delay..fade_end]) < 0.5);
+    assert!((peak(&samples[fade_end..]) - 0.5).abs() < 0.01);
+
+    // Six wobbles a second cross zero upwards six times
+    let second = &samples[SAMPLE_RATE as usize..];
+    let upward = second
+        .windows(2)
+        .filter(|pair| pair[0] < 0. && pair[1] >= 0.)
+        .count();
+    assert_eq!(upward, 6);
+
+    vibrato.restart();
+    assert_eq!(vibrato.next(), 0);
+}
+
+#[test]
+fn test_shake_detector() {
+    let mut shake = ShakeDetector::default();
+
+    let mut now = 0;
+
+    // A steady crescendo is not a shake
+    for step in 0..20 {
+        now += UPDATE_INTERVAL;
+        let depth = shake.update(Some(BlowStrength::from_num(0.3 + step as f64 * 0.01)), now);
+        assert_eq!(depth, 0);
+    }
+
+    // Wobbling the pot by 0.1 every 100ms gives half depth
+    let mut depth = U0F16::ZERO;
+    for update in 0..60 {
+        now += UPDATE_INTERVAL;
+        let strength = if (update / 10) & 1 == 0 { 0.5 } else { 0.6 };
+        depth = shake.update(Some(BlowStrength::from_num(strength)), now);
+    }
+    assert!((depth.to_num::<f64>() - 0.5).abs() < 0.01);
+
+    // However often it is read in between
+    for _ in 0..50 {
+        depth = shake.update(None, now);
+    }
+    assert!((depth.to_num::<f64>() - 0.5).abs() < 0.01);
+
+    // Holding still lets it die out
+    for _ in 0..30 {
+        now += UPDATE_INTERVAL;
+        depth = shake.update(None, now);
+    }
+    assert_eq!(depth, 0);
+}
+
+#[test]
+fn test_vibrato_settings_reach_synth() {
+    let mut trumpet = Trumpet::new(BFLAT_TRUMPET);
+    let commands = trumpet.tick(&[
+        TrumpetEvent::BlowDown,
+        TrumpetEvent::BlowStrengthChange(BlowStrength::from_num(0.5)),
+        TrumpetEvent::EmbouchureChange(Embouchure::from_num(0.25)),
+    ]);
+    // Attack and frequency, no vibrato by default
+    assert_eq!(commands.len(), 2);
+
+    let settings = VibratoSettings {
+        depth: U0F16::from_num(0.4),
+        ..Default::default()
+    };
+    trumpet.configure(TrumpetSetting::Vibrato(settings));
+    assert_eq!(trumpet.tick(&[]).len(), 1);
+    assert_eq!(trumpet.tick(&[]).len(), 0);
+
+    // A dedicated input scales the depth and skips the delay
+    trumpet.configure(TrumpetSetting::VibratoControl(VibratoControl::Input));
+    trumpet.tick(&[TrumpetEvent::VibratoChange(U0F16::from_num(0.5))]);
+    let vibrato = trumpet.vibrato();
+    assert!((vibrato.depth.to_num::<f64>() - 0.2).abs() < 0.001);
+    assert_eq!(vibrato.delay, 0);
+}
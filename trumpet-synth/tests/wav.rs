@@ -1,15 +1,18 @@
 use std::{
     collections::VecDeque,
+    convert::Infallible,
     sync::{
-        atomic::{AtomicBool, AtomicU16, Ordering},
+        atomic::{AtomicBool, AtomicU16, AtomicU32, Ordering},
         Arc, Mutex,
     },
 };
 
+use fixed::types::U12F4;
 use rytmos_synth::{commands::Command, synth::Synth};
 use trumpet_synth::{
     interface::TrumpetInterface,
-    io::{Fifo, Inputs, IO},
+    io::{Clock, Fifo, Inputs, Millis, IO},
+    presets::{self, TrumpetPreset},
     trumpet::{BlowStrength, Embouchure, Valve},
 };
 
@@ -18,9 +21,25 @@ struct TestFifo {
 }
 
 impl Fifo for TestFifo {
-    fn write(&mut self, value: u32) {
+    type Error = Infallible;
+
+    fn write(&mut self, value: u32) -> Result<(), Infallible> {
         let mut guard = self.fifo.lock().unwrap();
         guard.push_back(value);
+        Ok(())
+    }
+}
+
+const SAMPLE_RATE: u32 = 24000;
+
+/// Time passes with the samples rendered.
+struct TestClock {
+    samples: Arc<AtomicU32>,
+}
+
+impl Clock for TestClock {
+    fn now(&mut self) -> Millis {
+        self.samples.load(Ordering::Relaxed) / (SAMPLE_RATE / 1000)
     }
 }
 
@@ -29,6 +48,7 @@ struct SharedTestInputs {
     valve1: AtomicBool,
     valve2: AtomicBool,
     valve3: AtomicBool,
+    valve4: AtomicBool,
     embouchure: AtomicU16,
     blowstrength: AtomicU16,
 }
@@ -38,24 +58,31 @@ struct TestInputs {
 }
 
 impl Inputs for TestInputs {
-    fn valve(&mut self, valve: Valve) -> bool {
-        match valve {
+    type Error = Infallible;
+
+    fn valve(&mut self, valve: Valve) -> Result<bool, Infallible> {
+        Ok(match valve {
             Valve::First => self.inputs.valve1.load(Ordering::Relaxed),
             Valve::Second => self.inputs.valve2.load(Ordering::Relaxed),
             Valve::Third => self.inputs.valve3.load(Ordering::Relaxed),
-        }
+            Valve::Fourth => self.inputs.valve4.load(Ordering::Relaxed),
+        })
     }
 
-    fn blow(&mut self) -> bool {
-        self.inputs.blow.load(Ordering::Relaxed)
+    fn blow(&mut self) -> Result<bool, Infallible> {
+        Ok(self.inputs.blow.load(Ordering::Relaxed))
     }
 
-    fn embouchure(&mut self) -> Embouchure {
-        Embouchure::from_bits(self.inputs.embouchure.load(Ordering::Relaxed))
+    fn embouchure(&mut self) -> Result<Embouchure, Infallible> {
+        Ok(Embouchure::from_bits(
+            self.inputs.embouchure.load(Ordering::Relaxed),
+        ))
     }
 
-    fn blowstrength(&mut self) -> trumpet_synth::trumpet::BlowStrength {
-        BlowStrength::from_bits(self.inputs.blowstrength.load(Ordering::Relaxed))
+    fn blowstrength(&mut self) -> Result<trumpet_synth::trumpet::BlowStrength, Infallible> {
+        Ok(BlowStrength::from_bits(
+            self.inputs.blowstrength.load(Ordering::Relaxed),
+        ))
     }
 }
 
@@ -72,22 +99,34 @@ pub struct TrumpetSynthTester {
     synthesizer: trumpet_synth::synth::TrumpetSynth,
     fifo: Arc<Mutex<VecDeque<u32>>>,
     inputs: Arc<SharedTestInputs>,
-    interface: TrumpetInterface<TestFifo, TestInputs>,
+    samples: Arc<AtomicU32>,
+    interface: TrumpetInterface<TestFifo, TestInputs, TestClock>,
     tester_input: VecDeque<TesterInput>,
+    /// Frequency of the synth at every rendered sample.
+    frequencies: Vec<U12F4>,
 }
 
 impl TrumpetSynthTester {
     pub fn new(tester_input: VecDeque<TesterInput>) -> Self {
+        Self::with_preset(
+            presets::by_name("bb_trumpet").expect("Bb trumpet preset exists"),
+            tester_input,
+        )
+    }
+
+    pub fn with_preset(preset: &TrumpetPreset, tester_input: VecDeque<TesterInput>) -> Self {
         let fifo = Arc::new(Mutex::new(VecDeque::new()));
         let inputs = Arc::new(SharedTestInputs {
             blow: AtomicBool::new(false),
             valve1: AtomicBool::new(false),
             valve2: AtomicBool::new(false),
             valve3: AtomicBool::new(false),
+            valve4: AtomicBool::new(false),
             embouchure: AtomicU16::new(0),
             blowstrength: AtomicU16::new(0),
         });
-        let interface = TrumpetInterface::new(
+        let samples = Arc::new(AtomicU32::new(0));
+        let interface = TrumpetInterface::with_preset(
             IO {
                 fifo: TestFifo {
                     fifo: Arc::clone(&fifo),
@@ -95,16 +134,22 @@ impl TrumpetSynthTester {
                 inputs: TestInputs {
                     inputs: Arc::clone(&inputs),
                 },
+                clock: TestClock {
+                    samples: Arc::clone(&samples),
+                },
             },
             0,
+            preset,
         );
 
         Self {
             synthesizer: trumpet_synth::synth::create(),
             fifo,
+            samples,
             interface,
             tester_input,
             inputs: inputs,
+            frequencies: Vec::new(),
         }
     }
 
@@ -113,7 +158,7 @@ impl TrumpetSynthTester {
 
         let spec = hound::WavSpec {
             channels: 1,
-            sample_rate: 24000,
+            sample_rate: SAMPLE_RATE,
             bits_per_sample: 16,
             sample_format: hound::SampleFormat::Int,
         };
@@ -132,7 +177,7 @@ impl TrumpetSynthTester {
             dbg!(&inp);
             result.extend(self.handle_input(inp));
 
-            self.interface.run();
+            self.interface.run().unwrap();
 
             let mut queue = self.fifo.lock().unwrap();
 
@@ -153,13 +198,16 @@ impl TrumpetSynthTester {
             TesterInput::NoInput { samples } => {
                 for _ in 0..samples {
                     result.push(self.synthesizer.next().to_bits());
+                    self.frequencies.push(self.synthesizer.frequency());
                 }
+                self.samples.fetch_add(samples, Ordering::Relaxed);
             }
             TesterInput::Blow(state) => self.inputs.blow.store(state, Ordering::Relaxed),
             TesterInput::Valve { valve, state } => match valve {
                 Valve::First => self.inputs.valve1.store(state, Ordering::Relaxed),
                 Valve::Second => self.inputs.valve2.store(state, Ordering::Relaxed),
                 Valve::Third => self.inputs.valve3.store(state, Ordering::Relaxed),
+                Valve::Fourth => self.inputs.valve4.store(state, Ordering::Relaxed),
             },
             TesterInput::Embouchure(value) => {
                 self.inputs.embouchure.store(value, Ordering::Relaxed)
@@ -205,3 +253,44 @@ fn test_trumpet_frequency() {
 
     tester.run_to_wav("out.wav").unwrap();
 }
+
+#[test]
+fn test_lip_slur_glides() {
+    let mut input = vec![
+        TesterInput::Embouchure(0x2000),
+        TesterInput::Blowstrength(0x7fff),
+        TesterInput::Blow(true),
+        TesterInput::NoInput { samples: 4800 },
+    ];
+
+    // Sweep up over several partials in 10ms steps
+    for embouchure in (0x2000..0x8000).step_by(0x100) {
+        input.push(TesterInput::Embouchure(embouchure));
+        input.push(TesterInput::NoInput { samples: 240 });
+    }
+    input.push(TesterInput::NoInput { samples: 4800 });
+
+    let mut tester = TrumpetSynthTester::new(input.into());
+    tester.run_to_wav("slur.wav").unwrap();
+
+    let frequencies: Vec<f64> = tester
+        .frequencies
+        .iter()
+        .map(|frequency| frequency.to_num::<f64>())
+        .collect();
+
+    let lowest = frequencies.iter().cloned().fold(f64::MAX, f64::min);
+    let highest = frequencies.iter().cloned().fold(0., f64::max);
+    assert!(highest > lowest * 2., "the sweep crosses several partials");
+
+    // Partials lie a third to an octave apart, the glide never moves more
+    // than a few percent at once
+    for pair in frequencies.windows(2) {
+        assert!(
+            pair[1] / pair[0] < 1.05,
+            "jump from {} to {}",
+            pair[0],
+            pair[1]
+        );
+    }
+}
@@ -1,10 +1,12 @@
+use std::convert::Infallible;
+
 use dioxus::signals::{Readable, Signal};
 use fixed::types::U0F16;
 use trumpet_synth::{
     io,
-    trumpet::{BlowStrength, Embouchure, Valve},
+    trumpet::{BlowStrength, Embouchure, SlideExtension, Valve},
 };
-use web_sys::{wasm_bindgen::JsValue, AudioWorkletNode};
+use web_sys::{wasm_bindgen::JsValue, window, AudioWorkletNode, Performance};
 
 // TODO: common between polypicophonic and trumpet_synth
 pub struct WebFifo {
@@ -17,14 +19,47 @@ impl WebFifo {
     }
 }
 
+#[derive(Debug)]
+pub enum WebFifoError {
+    /// The audio node is created once the user starts the audio.
+    NotInitialized,
+    Port(JsValue),
+}
+
 impl io::Fifo for WebFifo {
-    fn write(&mut self, value: u32) {
+    type Error = WebFifoError;
+
+    fn write(&mut self, value: u32) -> Result<(), WebFifoError> {
         let binding = self.node.read();
-        let node = binding.as_ref().unwrap();
+        let node = binding.as_ref().ok_or(WebFifoError::NotInitialized)?;
         node.port()
-            .unwrap()
-            .post_message(&JsValue::from_f64(value as f64))
-            .unwrap();
+            .and_then(|port| port.post_message(&JsValue::from_f64(value as f64)))
+            .map_err(WebFifoError::Port)
+    }
+}
+
+/// Time since the page loaded.
+pub struct WebClock {
+    performance: Performance,
+}
+
+impl WebClock {
+    pub fn new() -> Self {
+        Self {
+            performance: window().unwrap().performance().unwrap(),
+        }
+    }
+}
+
+impl Default for WebClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl io::Clock for WebClock {
+    fn now(&mut self) -> io::Millis {
+        self.performance.now() as io::Millis
     }
 }
 
@@ -36,26 +71,41 @@ pub struct WebInputs {
     pub blow_signal: Signal<bool>,
     pub embouchure_signal: Signal<f64>,
     pub blowstrength_signal: Signal<f64>,
+    /// How far the first valve slide, or the main slide of a slide
+    /// instrument, is pulled out from 0 to 1.
+    pub first_slide_signal: Signal<f64>,
+    pub third_slide_signal: Signal<bool>,
 }
 
 impl io::Inputs for WebInputs {
-    fn valve(&mut self, valve: Valve) -> bool {
-        match valve {
-            Valve::First => (*self.first_valve_signal.read()).into(),
-            Valve::Second => (*self.second_valve_signal.read()).into(),
-            Valve::Third => (*self.third_valve_signal.read()).into(),
-        }
+    type Error = Infallible;
+
+    fn valve(&mut self, valve: Valve) -> Result<bool, Infallible> {
+        Ok(match valve {
+            Valve::First => *self.first_valve_signal.read(),
+            Valve::Second => *self.second_valve_signal.read(),
+            Valve::Third => *self.third_valve_signal.read(),
+            Valve::Fourth => false,
+        })
+    }
+
+    fn blow(&mut self) -> Result<bool, Infallible> {
+        Ok(*self.blow_signal.read())
     }
 
-    fn blow(&mut self) -> bool {
-        return (*self.blow_signal.read()).into();
+    fn embouchure(&mut self) -> Result<Embouchure, Infallible> {
+        Ok(U0F16::from_num(*self.embouchure_signal.read()))
     }
 
-    fn embouchure(&mut self) -> Embouchure {
-        U0F16::from_num(*self.embouchure_signal.read())
+    fn blowstrength(&mut self) -> Result<BlowStrength, Infallible> {
+        Ok(U0F16::from_num(*self.blowstrength_signal.read()))
     }
 
-    fn blowstrength(&mut self) -> BlowStrength {
-        U0F16::from_num(*self.blowstrength_signal.read())
+    fn slide(&mut self, valve: Valve) -> Result<SlideExtension, Infallible> {
+        Ok(match valve {
+            Valve::First => SlideExtension::saturating_from_num(*self.first_slide_signal.read()),
+            Valve::Third if *self.third_slide_signal.read() => SlideExtension::MAX,
+            _ => SlideExtension::ZERO,
+        })
     }
 }
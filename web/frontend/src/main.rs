@@ -10,17 +10,27 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use dioxus::prelude::*;
+use fixed::types::{U0F16, U24F8};
 #[allow(unused_imports)]
 use tracing::info;
-use trumpet_synth::interface::TrumpetInterface;
+use trumpet_synth::assist::PitchAssist;
+use trumpet_synth::calibration::Calibration;
+use trumpet_synth::effects::{EffectBinding, Effects, Gesture};
+use trumpet_synth::environment::{Celsius, Environment};
+use trumpet_synth::interface::{InterfaceError, TrumpetInterface};
 use trumpet_synth::io::IO;
-use trumpet_synth_web::io::{WebFifo, WebInputs};
+use trumpet_synth::pitch::{self, Cents};
+use trumpet_synth::presets::{self, PRESETS};
+use trumpet_synth::trumpet::TrumpetSetting;
+use trumpet_synth::vibrato::VibratoSettings;
+use trumpet_synth_web::io::{WebClock, WebFifo, WebFifoError, WebInputs};
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::js_sys::Array;
 use web_sys::wasm_bindgen::JsCast;
 use web_sys::{
-    window, AudioContext, AudioWorkletNode, AudioWorkletNodeOptions, Request, RequestInit, Response,
+    window, AudioContext, AudioWorkletNode, AudioWorkletNodeOptions, Request, RequestInit,
+    Response, Storage,
 };
 
 pub struct AudioSetup {
@@ -131,6 +141,8 @@ impl InputBehavior {
         bool_to_set: bool,
     ) {
         // keys comma, period, slash for the valves
+        // semicolon and quote pull the first and third valve slides fully out,
+        // the slide slider sets the first one in between
         // embouchure slider is asdfghj
         // blowstrength slider is zxcvbnm
         // TODO: map embouchure and blowstrength to xbox controller joystick
@@ -139,6 +151,11 @@ impl InputBehavior {
             "Period" => self.signals.second_valve_signal.set(bool_to_set),
             "Slash" => self.signals.third_valve_signal.set(bool_to_set),
             "Space" => self.signals.blow_signal.set(bool_to_set),
+            "Semicolon" => self
+                .signals
+                .first_slide_signal
+                .set(if bool_to_set { 1. } else { 0. }),
+            "Quote" => self.signals.third_slide_signal.set(bool_to_set),
             _ => (),
         }
 
@@ -229,6 +246,17 @@ fn app() -> Element {
     let blow_signal = use_signal(|| false);
     let embouchure_signal = use_signal(|| 0.0);
     let blowstrength_signal = use_signal(|| 0.0);
+    let mut first_slide_signal = use_signal(|| 0.);
+    let third_slide_signal = use_signal(|| false);
+    let mut preset_signal = use_signal(|| 0usize);
+    let mut note_signal = use_signal(String::new);
+    let mut temperature_signal = use_signal(|| Environment::ROOM.temperature.to_num::<f64>());
+    let mut reference_pitch_signal =
+        use_signal(|| load_setting(REFERENCE_PITCH_KEY, pitch::CONCERT_A.to_num::<f64>()));
+    let mut transpose_signal = use_signal(|| load_setting(TRANSPOSE_KEY, 0.));
+    let mut vibrato_signal = use_signal(|| 0.);
+    let mut lungs_signal = use_signal(|| false);
+    let mut assist_signal = use_signal(|| 0.);
 
     let inputs = WebInputs {
         first_valve_signal,
@@ -237,6 +265,8 @@ fn app() -> Element {
         blow_signal,
         embouchure_signal,
         blowstrength_signal,
+        first_slide_signal,
+        third_slide_signal,
     };
 
     let input_behavior = Arc::new(Mutex::new(InputBehavior::new(inputs.clone())));
@@ -251,15 +281,104 @@ fn app() -> Element {
                 let io = IO {
                     fifo: WebFifo::new(audio_setup.node_signal),
                     inputs,
+                    clock: WebClock::new(),
                 };
 
-                let mut interface = TrumpetInterface::new(io, 1);
+                let mut preset_index = *preset_signal.read();
+                let preset = presets::by_index(preset_index).unwrap_or(&PRESETS[0]);
+                let mut interface = TrumpetInterface::with_preset(io, 5, preset);
+                if let Some(calibration) = load_calibration() {
+                    interface.set_calibration(calibration);
+                }
+                let mut calibration = *interface.calibration();
+                let mut temperature = Environment::ROOM.temperature.to_num::<f64>();
+                let mut reference_pitch = pitch::CONCERT_A.to_num::<f64>();
+                let mut transpose = 0.;
+                let mut vibrato = 0.;
+                let mut lungs = false;
+                let mut assist = 0.;
 
                 const MILLIS_PER_ITER: u64 = 10;
                 let mut dt = MILLIS_PER_ITER;
 
                 loop {
-                    interface.run();
+                    let selected_preset = *preset_signal.read();
+                    if selected_preset != preset_index {
+                        if let Some(preset) = presets::by_index(selected_preset) {
+                            interface.set_preset(preset);
+                        }
+                        preset_index = selected_preset;
+                    }
+
+                    let selected_temperature = *temperature_signal.read();
+                    if selected_temperature != temperature {
+                        interface.configure(TrumpetSetting::Temperature(Celsius::from_num(
+                            selected_temperature,
+                        )));
+                        temperature = selected_temperature;
+                    }
+
+                    let selected_reference_pitch = *reference_pitch_signal.read();
+                    if selected_reference_pitch != reference_pitch {
+                        interface.configure(TrumpetSetting::ReferencePitch(U24F8::from_num(
+                            selected_reference_pitch,
+                        )));
+                        store_setting(REFERENCE_PITCH_KEY, selected_reference_pitch);
+                        reference_pitch = selected_reference_pitch;
+                    }
+
+                    let selected_transpose = *transpose_signal.read();
+                    if selected_transpose != transpose {
+                        interface.configure(TrumpetSetting::Transpose(Cents::from_num(
+                            selected_transpose,
+                        )));
+                        store_setting(TRANSPOSE_KEY, selected_transpose);
+                        transpose = selected_transpose;
+                    }
+
+                    let selected_vibrato = *vibrato_signal.read();
+                    if selected_vibrato != vibrato {
+                        interface.configure(TrumpetSetting::Vibrato(VibratoSettings {
+                            depth: U0F16::saturating_from_num(selected_vibrato),
+                            ..Default::default()
+                        }));
+                        vibrato = selected_vibrato;
+                    }
+
+                    let selected_lungs = *lungs_signal.read();
+                    if selected_lungs != lungs {
+                        interface.configure(TrumpetSetting::RealisticLungs(selected_lungs));
+                        lungs = selected_lungs;
+                    }
+
+                    let selected_assist = *assist_signal.read();
+                    if selected_assist != assist {
+                        interface.configure(TrumpetSetting::PitchAssist(PitchAssist {
+                            strength: U0F16::saturating_from_num(selected_assist),
+                            quantize: true,
+                        }));
+                        assist = selected_assist;
+                    }
+
+                    // Commands are held until the audio is started and the node exists
+                    match interface.run() {
+                        Ok(()) | Err(InterfaceError::Fifo(WebFifoError::NotInitialized)) => (),
+                        Err(error) => tracing::warn!("{error:?}"),
+                    }
+
+                    if *interface.calibration() != calibration {
+                        calibration = *interface.calibration();
+                        store_calibration(&calibration);
+                    }
+
+                    let note = interface
+                        .trumpet()
+                        .written_pitch()
+                        .map(|pitch| pitch.to_string())
+                        .unwrap_or_default();
+                    if *note_signal.peek() != note {
+                        note_signal.set(note);
+                    }
 
                     // If we can't lock, just skip this update, don't block
                     if let Ok(mut b) = input_behavior.try_lock() {
@@ -316,6 +435,11 @@ fn app() -> Element {
         }
     });
 
+    let effect_bindings: Vec<String> = Effects::DEFAULT_BINDINGS
+        .iter()
+        .map(binding_label)
+        .collect();
+
     rsx! {
         div {
             class: "content",
@@ -331,6 +455,22 @@ fn app() -> Element {
                     "Trumpet Synth"
                 }
 
+                select {
+                    class: "preset-select",
+                    onchange: move |event| {
+                        if let Ok(index) = event.value().parse::<usize>() {
+                            preset_signal.set(index);
+                        }
+                    },
+                    for (index, preset) in PRESETS.iter().enumerate() {
+                        option {
+                            value: "{index}",
+                            selected: index == *preset_signal.read(),
+                            "{preset.name}"
+                        }
+                    }
+                }
+
                 div {
                     style: "display: flex",
                     {valve_button(inputs.first_valve_signal)}
@@ -338,6 +478,133 @@ fn app() -> Element {
                     {valve_button(inputs.third_valve_signal)}
                 }
 
+                div {
+                    class: "note-display",
+                    "{note_signal}"
+                }
+
+                div {
+                    class: "setting",
+                    "Effects while playing:"
+                    for label in effect_bindings.iter() {
+                        div { "{label}" }
+                    }
+                }
+
+                label {
+                    class: "setting",
+                    "A = "
+                    input {
+                        r#type: "number",
+                        min: "380",
+                        max: "480",
+                        step: "1",
+                        value: "{reference_pitch_signal}",
+                        onchange: move |event| {
+                            if let Ok(reference_pitch) = event.value().parse::<f64>() {
+                                reference_pitch_signal.set(reference_pitch.clamp(380., 480.));
+                            }
+                        },
+                    }
+                    " Hz"
+                }
+
+                label {
+                    class: "setting",
+                    "Transpose "
+                    input {
+                        r#type: "number",
+                        min: "-1200",
+                        max: "1200",
+                        step: "10",
+                        value: "{transpose_signal}",
+                        onchange: move |event| {
+                            if let Ok(transpose) = event.value().parse::<f64>() {
+                                transpose_signal.set(transpose.clamp(-1200., 1200.));
+                            }
+                        },
+                    }
+                    " cents"
+                }
+
+                label {
+                    class: "setting",
+                    input {
+                        r#type: "range",
+                        min: "-10",
+                        max: "40",
+                        step: "1",
+                        value: "{temperature_signal}",
+                        oninput: move |event| {
+                            if let Ok(temperature) = event.value().parse::<f64>() {
+                                temperature_signal.set(temperature);
+                            }
+                        },
+                    }
+                    "{temperature_signal} °C"
+                }
+
+                label {
+                    class: "setting",
+                    "Vibrato "
+                    input {
+                        r#type: "range",
+                        min: "0",
+                        max: "1",
+                        step: "0.05",
+                        value: "{vibrato_signal}",
+                        oninput: move |event| {
+                            if let Ok(vibrato) = event.value().parse::<f64>() {
+                                vibrato_signal.set(vibrato);
+                            }
+                        },
+                    }
+                }
+
+                label {
+                    class: "setting",
+                    input {
+                        r#type: "checkbox",
+                        checked: "{lungs_signal}",
+                        onchange: move |event| lungs_signal.set(event.checked()),
+                    }
+                    " Realistic lungs"
+                }
+
+                label {
+                    class: "setting",
+                    "Pitch assist "
+                    input {
+                        r#type: "range",
+                        min: "0",
+                        max: "1",
+                        step: "0.05",
+                        value: "{assist_signal}",
+                        oninput: move |event| {
+                            if let Ok(assist) = event.value().parse::<f64>() {
+                                assist_signal.set(assist);
+                            }
+                        },
+                    }
+                }
+
+                label {
+                    class: "setting",
+                    "Slide "
+                    input {
+                        r#type: "range",
+                        min: "0",
+                        max: "1",
+                        step: "0.01",
+                        value: "{first_slide_signal}",
+                        oninput: move |event| {
+                            if let Ok(extension) = event.value().parse::<f64>() {
+                                first_slide_signal.set(extension);
+                            }
+                        },
+                    }
+                }
+
                 {slider(30., inputs.embouchure_signal, "red")}
                 {slider(30., inputs.blowstrength_signal, "blue")}
                 {valve_button(inputs.blow_signal)}
@@ -355,6 +622,26 @@ fn app() -> Element {
     }
 }
 
+/// Describes a binding with the keys that play it, e.g. "3× , → Shake".
+fn binding_label(binding: &EffectBinding) -> String {
+    const VALVE_KEYS: [&str; 3] = [",", ".", "/"];
+
+    let gesture = match binding.gesture {
+        Gesture::ValveFlutter(valve) => {
+            format!("3× {}", VALVE_KEYS.get(valve as usize).unwrap_or(&"?"))
+        }
+        Gesture::Combination(valves) => {
+            let keys: Vec<&str> = (0..VALVE_KEYS.len())
+                .filter(|&valve| valves & (1 << valve) != 0)
+                .map(|valve| VALVE_KEYS[valve])
+                .collect();
+            format!("hold {}", keys.join(" "))
+        }
+    };
+
+    format!("{} → {:?}", gesture, binding.effect)
+}
+
 fn valve_button(valve: Signal<bool>) -> Element {
     let class = if *valve.read() {
         "valve-down"
@@ -379,6 +666,53 @@ fn slider(width_scale: f64, value: Signal<f64>, color: &str) -> Element {
     }
 }
 
+const REFERENCE_PITCH_KEY: &str = "trumpet-synth.reference-pitch";
+const TRANSPOSE_KEY: &str = "trumpet-synth.transpose";
+const CALIBRATION_KEY: &str = "trumpet-synth.calibration";
+
+fn local_storage() -> Option<Storage> {
+    window()?.local_storage().ok()?
+}
+
+/// Reads a setting saved in an earlier session.
+fn load_setting(key: &str, default: f64) -> f64 {
+    local_storage()
+        .and_then(|storage| storage.get_item(key).ok()?)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn store_setting(key: &str, value: f64) {
+    if let Some(storage) = local_storage() {
+        // Settings just don't persist if storage is unavailable or full
+        let _ = storage.set_item(key, &value.to_string());
+    }
+}
+
+/// Reads the pot calibration of an earlier session, stored as hex.
+fn load_calibration() -> Option<Calibration> {
+    let hex = local_storage()?.get_item(CALIBRATION_KEY).ok()??;
+
+    let mut bytes = [0; Calibration::SIZE];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    Calibration::from_bytes(&bytes).ok()
+}
+
+fn store_calibration(calibration: &Calibration) {
+    let hex: String = calibration
+        .to_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    if let Some(storage) = local_storage() {
+        let _ = storage.set_item(CALIBRATION_KEY, &hex);
+    }
+}
+
 fn main() {
     dioxus::launch(app);
 }